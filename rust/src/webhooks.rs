@@ -1,6 +1,8 @@
 // SPDX-FileCopyrightText: © 2022 Svix Authors
 // SPDX-License-Identifier: MIT
 
+use std::time::Duration;
+
 use http::HeaderMap;
 use time::OffsetDateTime;
 
@@ -33,6 +35,8 @@ pub enum WebhookError {
 
 pub struct Webhook {
     key: Vec<u8>,
+    /// How far the signed timestamp may be from the current time, in either direction
+    tolerance_in_seconds: i64,
 }
 
 const PREFIX: &str = "whsec_";
@@ -50,7 +54,18 @@ impl Webhook {
         let secret = secret.strip_prefix(PREFIX).unwrap_or(&secret);
         let key = base64::decode(secret)?;
 
-        Ok(Webhook { key })
+        Ok(Webhook {
+            key,
+            tolerance_in_seconds: TOLERANCE_IN_SECONDS,
+        })
+    }
+
+    /// Sets how far the signed timestamp may be from the current time (5 minutes by default).
+    /// Deliveries signed longer ago are rejected as replays, and ones signed further in the future
+    /// as forged, so it should only be raised to account for clock skew.
+    pub fn with_tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance_in_seconds = i64::try_from(tolerance.as_secs()).unwrap_or(i64::MAX);
+        self
     }
 
     pub fn verify(&self, payload: &[u8], headers: &HeaderMap) -> Result<(), WebhookError> {
@@ -69,7 +84,7 @@ impl Webhook {
         )
         .and_then(Self::parse_timestamp)?;
 
-        Self::verify_timestamp(msg_ts)?;
+        self.verify_timestamp(msg_ts)?;
 
         let versioned_signature = self.sign(msg_id, msg_ts, payload)?;
         let expected_signature = versioned_signature
@@ -123,11 +138,11 @@ impl Webhook {
         str::parse::<i64>(hdr).map_err(|_| WebhookError::InvalidTimestamp)
     }
 
-    fn verify_timestamp(ts: i64) -> Result<(), WebhookError> {
+    fn verify_timestamp(&self, ts: i64) -> Result<(), WebhookError> {
         let now = OffsetDateTime::now_utc().unix_timestamp();
-        if now - ts > TOLERANCE_IN_SECONDS {
+        if now.saturating_sub(ts) > self.tolerance_in_seconds {
             Err(WebhookError::TimestampTooOldError)
-        } else if ts > now + TOLERANCE_IN_SECONDS {
+        } else if ts.saturating_sub(now) > self.tolerance_in_seconds {
             Err(WebhookError::FutureTimestampError)
         } else {
             Ok(())
@@ -244,6 +259,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_verify_with_tolerance() {
+        let secret = "whsec_C2FVsBQIhrscChlQIMV+b5sSYspob7oD".to_owned();
+        let msg_id = "msg_27UH4WbU6Z5A5EzD8u03UvzRbpk";
+        let payload = br#"{"email":"test@example.com","username":"test_user"}"#;
+        let wh = Webhook::new(secret)
+            .unwrap()
+            .with_tolerance(Duration::from_secs(60));
+
+        let verify = |ts: i64| {
+            let signature = wh.sign(msg_id, ts, payload).unwrap();
+            let mut headers = get_svix_headers(msg_id, &signature);
+            headers.insert(SVIX_MSG_TIMESTAMP_KEY, ts.to_string().parse().unwrap());
+            wh.verify(payload, &headers)
+        };
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        // In the window, on either side
+        assert!(verify(now - 30).is_ok());
+        assert!(verify(now + 30).is_ok());
+        // Too old, even though within the default tolerance
+        assert!(matches!(
+            verify(now - 120),
+            Err(WebhookError::TimestampTooOldError)
+        ));
+        // Future-dated beyond the tolerance
+        assert!(matches!(
+            verify(now + 120),
+            Err(WebhookError::FutureTimestampError)
+        ));
+    }
+
     #[test]
     fn test_verify_with_multiple_signatures() {
         let secret = "whsec_C2FVsBQIhrscChlQIMV+b5sSYspob7oD".to_owned();
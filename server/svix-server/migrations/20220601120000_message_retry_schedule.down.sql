@@ -0,0 +1,2 @@
+ALTER TABLE messageattempt DROP COLUMN custom_retry_schedule;
+ALTER TABLE message DROP COLUMN retry_schedule;
@@ -0,0 +1,2 @@
+ALTER TABLE message ADD COLUMN retry_schedule jsonb;
+ALTER TABLE messageattempt ADD COLUMN custom_retry_schedule boolean NOT NULL DEFAULT false;
@@ -0,0 +1 @@
+ALTER TABLE endpoint ADD COLUMN drain_deadline timestamptz;
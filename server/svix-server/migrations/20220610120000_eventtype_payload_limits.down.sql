@@ -0,0 +1,2 @@
+ALTER TABLE eventtype DROP COLUMN max_payload_array_length;
+ALTER TABLE eventtype DROP COLUMN max_payload_depth;
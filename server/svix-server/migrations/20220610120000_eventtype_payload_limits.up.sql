@@ -0,0 +1,2 @@
+ALTER TABLE eventtype ADD COLUMN max_payload_depth integer;
+ALTER TABLE eventtype ADD COLUMN max_payload_array_length integer;
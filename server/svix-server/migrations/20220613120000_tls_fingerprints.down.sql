@@ -0,0 +1,2 @@
+ALTER TABLE messageattempt DROP COLUMN tls_fingerprint;
+ALTER TABLE endpoint DROP COLUMN pinned_tls_fingerprint;
@@ -0,0 +1,2 @@
+ALTER TABLE endpoint ADD COLUMN pinned_tls_fingerprint text;
+ALTER TABLE messageattempt ADD COLUMN tls_fingerprint text;
@@ -0,0 +1 @@
+ALTER TABLE message ADD COLUMN no_matching_endpoints boolean NOT NULL DEFAULT false;
@@ -0,0 +1 @@
+ALTER TABLE messageattempt DROP COLUMN failure_kind;
@@ -0,0 +1 @@
+ALTER TABLE messageattempt ADD COLUMN failure_kind smallint;
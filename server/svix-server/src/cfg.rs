@@ -4,15 +4,21 @@
 use std::sync::Arc;
 
 use figment::{
-    providers::{Env, Format, Toml},
+    providers::{Env, Format, Serialized, Toml},
     Figment,
 };
 use std::time::Duration;
 
-use crate::{core::security::Keys, error::Result};
+use crate::{
+    core::{
+        security::{JwtAlgorithm, Keys},
+        types::OrganizationId,
+    },
+    error::Result,
+};
 use serde::{Deserialize, Deserializer};
 use tracing::Level;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 fn deserialize_jwt_secret<'de, D>(deserializer: D) -> std::result::Result<Keys, D::Error>
 where
@@ -23,6 +29,22 @@ where
     Ok(Keys::new(buf.as_bytes()))
 }
 
+fn validate_jwt_secret(keys: &Keys) -> std::result::Result<(), ValidationError> {
+    if keys.secret_len() < Keys::MIN_SECRET_LENGTH {
+        let mut err = ValidationError::new("jwt_secret_too_short");
+        err.message = Some(
+            format!(
+                "The JWT secret must be at least {} bytes long, got {}",
+                Keys::MIN_SECRET_LENGTH,
+                keys.secret_len()
+            )
+            .into(),
+        );
+        return Err(err);
+    }
+    Ok(())
+}
+
 #[derive(Deserialize)]
 #[serde(untagged)]
 enum RetryScheduleDeserializer {
@@ -71,7 +93,85 @@ pub struct ConfigurationInner {
 
     /// The JWT secret for authentication - should be secret and securely generated
     #[serde(deserialize_with = "deserialize_jwt_secret")]
+    #[validate(custom = "validate_jwt_secret")]
     pub jwt_secret: Keys,
+    /// The HMAC algorithm tokens are signed with. Supported: hs256, hs384, hs512. Changing it
+    /// invalidates all previously issued tokens.
+    pub jwt_algorithm: JwtAlgorithm,
+
+    /// The organization operational webhooks are sent from, and management tokens are issued for
+    #[validate]
+    pub management_org_id: OrganizationId,
+
+    /// Further issuers (`iss`) whose tokens are accepted, besides the ones issued by this server
+    pub jwt_allowed_issuers: Vec<String>,
+
+    /// If true, and no `jwt_secret` is configured, tokens are signed with the publicly known
+    /// [`Keys::DEV_SECRET`] and an organization token is logged at startup. It's only honored in
+    /// debug builds, so it can never be enabled in production.
+    pub dev_mode: bool,
+
+    /// The URL of an identity provider's key set (JWKS). When set, RS256 tokens with a `kid` are
+    /// verified with the provider's keys, and their claims are mapped onto an organization (and
+    /// optionally an application). The provider's issuer has to be in `jwt_allowed_issuers`.
+    pub jwks_url: Option<String>,
+    /// How long the key set is cached for (in seconds) when the provider doesn't say
+    pub jwks_cache_ttl: u64,
+    /// The minimum time between fetching the key set (in seconds), e.g. on unknown `kid`s
+    pub jwks_min_refresh_interval: u64,
+    /// The claim of the provider's tokens holding the organization id
+    pub jwks_org_id_claim: String,
+    /// The claim of the provider's tokens holding the application id, for application tokens
+    pub jwks_app_id_claim: Option<String>,
+
+    /// If true, application tokens without a `scope` claim are rejected, so every application
+    /// token has to be issued for what it's used for
+    pub require_app_token_scope: bool,
+
+    /// The environment of tokens without an `env` claim, which tokens issued before environments
+    /// existed are for
+    pub default_environment: String,
+    /// The environment this server serves, if any. Tokens for other environments are rejected.
+    pub environment: Option<String>,
+
+    /// If true, tokens without a `kind` claim (minted before it was introduced) are rejected
+    pub require_token_kind: bool,
+
+    /// If true, the server is behind a trusted proxy, so the client's address is taken from the
+    /// `X-Forwarded-For` header rather than the connection's peer address
+    pub trust_forwarded_for: bool,
+
+    /// The maximum length (in bytes) of the `Authorization` header, longer ones are rejected
+    /// without being parsed
+    #[validate(range(min = 64))]
+    pub jwt_max_header_length: usize,
+    /// How long verifying a token may take (in milliseconds) before the request is rejected
+    #[validate(range(min = 1))]
+    pub jwt_verification_timeout: u64,
+
+    /// How many consecutive authentication failures a client's address may have before it's
+    /// blocked for a while, without any more tokens being verified. Disabled when 0.
+    /// Without `trust_forwarded_for`, all clients behind a load balancer share one address.
+    pub auth_failure_threshold: u32,
+    /// How long (in seconds) a client is first blocked for, doubling with every further failure
+    #[validate(range(min = 1))]
+    pub auth_failure_backoff: u64,
+    /// The longest (in seconds) a client is blocked for
+    #[validate(range(min = 1))]
+    pub auth_failure_max_backoff: u64,
+
+    /// How many verified tokens to keep in memory, so a token's signature isn't verified again on
+    /// every request. The cache is disabled when 0.
+    pub jwt_cache_size: usize,
+    /// How long a verified token is cached for (in seconds). Tokens are never cached past their
+    /// expiry.
+    #[validate(range(min = 1))]
+    pub jwt_cache_ttl: u64,
+
+    /// How long applications resolved from request paths are cached for (in seconds), sparing a
+    /// database lookup on every request. Updated or deleted applications are removed from the
+    /// cache right away. The cache is disabled when 0.
+    pub app_cache_ttl: u64,
 
     /// The log level to run the service with. Supported: info, debug, trace
     pub log_level: LogLevel,
@@ -104,6 +204,10 @@ pub struct ConfigurationInner {
 
     /// What kind of cache to use. Supported: memory, redis (must have redis_dsn configured), none.
     pub cache_type: CacheType,
+    /// How often (in seconds) expired entries are pruned from the cache, for cache types which
+    /// don't expire them on their own (i.e. "memory")
+    #[validate(range(min = 1))]
+    pub cache_prune_interval: u64,
 
     /// If true, headers are prefixed with `Webhook-`, otherwise with `Svix-` (default).
     pub whitelabel_headers: bool,
@@ -111,6 +215,18 @@ pub struct ConfigurationInner {
     /// If true, only allow https endpoints, otherwise also allow http.
     pub endpoint_https_only: bool,
 
+    /// How long an endpoint deleted with `?drain=true` keeps delivering its pending messages
+    /// before being removed (in seconds)
+    pub endpoint_drain_timeout: u32,
+
+    /// If true, send an operational webhook when an endpoint presents a different TLS certificate
+    /// than on its previous delivery. Endpoints with a pinned fingerprint are never alerted about.
+    pub endpoint_tls_fingerprint_alerts: bool,
+
+    /// If true, send an operational webhook when a message is created which matches none of its
+    /// application's endpoints. Such messages are always flagged with `noMatchingEndpoints`.
+    pub message_no_matching_endpoints_alerts: bool,
+
     /// How long to wait when making a request (in seconds)
     #[validate(range(min = 1, max = 30))]
     pub worker_request_timeout: u16,
@@ -125,6 +241,38 @@ pub struct ConfigurationInner {
     pub internal: InternalConfig,
 }
 
+impl ConfigurationInner {
+    /// Returns warnings about settings which are accepted but insecure. These are returned rather
+    /// than logged as configuration is loaded before tracing is set up.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.dev_mode_enabled() {
+            warnings.push(
+                "Dev mode is enabled, tokens are signed with a publicly known secret".to_owned(),
+            );
+        } else if self.dev_mode {
+            warnings.push(
+                "Dev mode is ignored, as it requires a debug build without a configured JWT secret"
+                    .to_owned(),
+            );
+        }
+        if self.jwt_secret.secret_len() < Keys::RECOMMENDED_SECRET_LENGTH {
+            warnings.push(format!(
+                "The JWT secret is only {} bytes long, at least {} bytes are recommended",
+                self.jwt_secret.secret_len(),
+                Keys::RECOMMENDED_SECRET_LENGTH
+            ));
+        }
+        warnings
+    }
+
+    /// Whether dev mode is in effect: it was asked for, this is a debug build, and tokens are
+    /// signed with the dev secret rather than a configured one
+    pub fn dev_mode_enabled(&self) -> bool {
+        cfg!(debug_assertions) && self.dev_mode && self.jwt_secret.is_dev_secret()
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct InternalConfig {
     /// The region to use in the Svix URL given in th dashboard access endpoint
@@ -195,12 +343,18 @@ pub fn load() -> Result<Arc<ConfigurationInner>> {
         }
     }
 
-    let config: ConfigurationInner = Figment::new()
+    let mut figment = Figment::new()
         .merge(Toml::string(DEFAULTS))
         .merge(Toml::file("config.toml"))
-        .merge(Env::prefixed("SVIX_"))
-        .extract()
-        .expect("Error loading configuration");
+        .merge(Env::prefixed("SVIX_"));
+    // Dev mode only ever fills in a missing secret, and never replaces a configured one
+    let dev_mode = figment.extract_inner::<bool>("dev_mode").unwrap_or(false);
+    if cfg!(debug_assertions) && dev_mode && figment.find_value("jwt_secret").is_err() {
+        figment = figment.join(Serialized::default("jwt_secret", Keys::DEV_SECRET));
+    }
+
+    let mut config: ConfigurationInner = figment.extract().expect("Error loading configuration");
+    config.jwt_secret = config.jwt_secret.into_algorithm(config.jwt_algorithm);
 
     config.validate().expect("Error validating configuration");
     Ok(Arc::from(config))
@@ -210,10 +364,12 @@ pub fn load() -> Result<Arc<ConfigurationInner>> {
 mod tests {
     use super::*;
 
+    const STRONG_SECRET: &str = "8KjzRXrKkd9YFcNyqLSIY8JwiaCeRc6WK4UkMnSW";
+
     #[test]
     fn test_retry_schedule_parsing() {
         figment::Jail::expect_with(|jail| {
-            jail.set_env("SVIX_JWT_SECRET", "x");
+            jail.set_env("SVIX_JWT_SECRET", STRONG_SECRET);
 
             // Multi item
             jail.set_env("SVIX_RETRY_SCHEDULE", "[1,2]");
@@ -243,7 +399,7 @@ mod tests {
     #[test]
     fn test_retry_schedule_parsing_legacy() {
         figment::Jail::expect_with(|jail| {
-            jail.set_env("SVIX_JWT_SECRET", "x");
+            jail.set_env("SVIX_JWT_SECRET", STRONG_SECRET);
 
             // Multi item
             jail.set_env("SVIX_RETRY_SCHEDULE", "1,2");
@@ -259,4 +415,67 @@ mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn test_jwt_secret_validation() {
+        figment::Jail::expect_with(|jail| {
+            // Too short
+            jail.set_env("SVIX_JWT_SECRET", "x");
+            let cfg: ConfigurationInner = Figment::new()
+                .merge(Toml::string(DEFAULTS))
+                .merge(Env::prefixed("SVIX_"))
+                .extract()
+                .unwrap();
+            assert!(cfg.validate().is_err());
+
+            // Marginal
+            jail.set_env("SVIX_JWT_SECRET", "0123456789abcdef");
+            let cfg = load().unwrap();
+            assert_eq!(cfg.warnings().len(), 1);
+
+            // Strong
+            jail.set_env("SVIX_JWT_SECRET", STRONG_SECRET);
+            let cfg = load().unwrap();
+            assert!(cfg.warnings().is_empty());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_jwt_algorithm() {
+        figment::Jail::expect_with(|jail| {
+            jail.set_env("SVIX_JWT_SECRET", STRONG_SECRET);
+
+            let cfg = load().unwrap();
+            assert_eq!(cfg.jwt_secret.algorithm(), JwtAlgorithm::Hs256);
+
+            jail.set_env("SVIX_JWT_ALGORITHM", "hs512");
+            let cfg = load().unwrap();
+            assert_eq!(cfg.jwt_secret.algorithm(), JwtAlgorithm::Hs512);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_management_org_id_validation() {
+        figment::Jail::expect_with(|jail| {
+            jail.set_env("SVIX_JWT_SECRET", STRONG_SECRET);
+
+            jail.set_env("SVIX_MANAGEMENT_ORG_ID", "app_2EhUTiNjrsCVDKRRauIWSP1dSpX");
+            let cfg: ConfigurationInner = Figment::new()
+                .merge(Toml::string(DEFAULTS))
+                .merge(Env::prefixed("SVIX_"))
+                .extract()
+                .unwrap();
+            assert!(cfg.validate().is_err());
+
+            jail.set_env("SVIX_MANAGEMENT_ORG_ID", "org_2EhUTiNjrsCVDKRRauIWSP1dSpX");
+            let cfg = load().unwrap();
+            assert_eq!(cfg.management_org_id.0, "org_2EhUTiNjrsCVDKRRauIWSP1dSpX");
+
+            Ok(())
+        });
+    }
 }
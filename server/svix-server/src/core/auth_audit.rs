@@ -0,0 +1,52 @@
+// SPDX-FileCopyrightText: © 2022 Svix Authors
+// SPDX-License-Identifier: MIT
+
+//! A durable record of authentication decisions. Every request authenticated through
+//! [`Permissions`](super::security::Permissions) is reported to the [`AuthAuditSink`] given to
+//! [`run_with_prefix`](crate::run_with_prefix), which deployments can point at a database, a message
+//! bus, etc. Events are recorded in the background, so a slow sink never delays requests. Unlike
+//! the tracing spans, it's meant to be stored and queried.
+
+use std::sync::Arc;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::{security::KeyType, types::OrganizationId};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthAuditOutcome {
+    Success,
+    Failure {
+        /// Why authentication failed, e.g. "expired_token"
+        reason: &'static str,
+    },
+}
+
+/// A single authentication decision. It never includes the token itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthAuditEvent {
+    /// The authenticated organization, only known on success
+    pub org_id: Option<OrganizationId>,
+    /// The type of the token, only known on success
+    pub key_type: Option<KeyType>,
+    /// The human-readable label of the token, if it has one
+    pub label: Option<String>,
+    pub outcome: AuthAuditOutcome,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait AuthAuditSink: Send + Sync {
+    async fn record(&self, event: AuthAuditEvent);
+}
+
+pub type SharedAuthAuditSink = Arc<dyn AuthAuditSink>;
+
+/// The default sink, which discards every event
+pub struct NoopAuthAuditSink;
+
+#[async_trait]
+impl AuthAuditSink for NoopAuthAuditSink {
+    async fn record(&self, _event: AuthAuditEvent) {}
+}
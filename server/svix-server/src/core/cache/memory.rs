@@ -4,7 +4,7 @@
 use tokio::{
     sync::RwLock,
     task,
-    time::{sleep, Duration, Instant},
+    time::{Duration, Instant},
 };
 
 use axum::async_trait;
@@ -33,21 +33,23 @@ impl ValueWrapper {
 type State = HashMap<Vec<u8>, ValueWrapper>;
 type SharedState = Arc<RwLock<State>>;
 
+/// How often expired entries are pruned from caches created with [`new`]
+const DEFAULT_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 5);
+
 pub fn new() -> Cache {
-    let shared_state = Arc::new(RwLock::new(State::new()));
-
-    let shared_state_clone = shared_state.clone();
-    task::spawn(async move {
-        loop {
-            sleep(Duration::from_secs(60 * 5)).await;
-            shared_state_clone
-                .write()
-                .await
-                .retain(|_, v| check_is_expired(v))
-        }
-    });
+    new_with_prune_interval(DEFAULT_PRUNE_INTERVAL)
+}
+
+/// Like [`new`], but pruning expired entries at the given interval instead of the default one
+pub fn new_with_prune_interval(interval: Duration) -> Cache {
+    let cache: Cache = MemoryCache {
+        map: Arc::new(RwLock::new(State::new())),
+    }
+    .into();
+
+    task::spawn(super::prune_expired_loop(cache.clone(), interval));
 
-    MemoryCache { map: shared_state }.into()
+    cache
 }
 
 #[derive(Clone)]
@@ -93,6 +95,12 @@ impl CacheBehavior for MemoryCache {
 
         Ok(())
     }
+
+    async fn prune_expired(&self) -> Result<()> {
+        self.map.write().await.retain(|_, v| check_is_expired(v));
+
+        Ok(())
+    }
 }
 
 fn check_is_expired(vw: &ValueWrapper) -> bool {
@@ -238,6 +246,32 @@ mod tests {
         assert_eq!(cache.get::<TestValA>(&key).await.unwrap(), None);
     }
 
+    #[tokio::test]
+    async fn test_prune_expired() {
+        let cache = MemoryCache {
+            map: Arc::new(RwLock::new(State::new())),
+        };
+        let expired = TestKeyA::new("expired_key".to_owned());
+        let unexpired = TestKeyA::new("unexpired_key".to_owned());
+
+        cache
+            .set(&expired, &TestValA(1), Duration::from_millis(100))
+            .await
+            .unwrap();
+        cache
+            .set(&unexpired, &TestValA(2), Duration::from_secs(30))
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        cache.prune_expired().await.unwrap();
+        let map = cache.map.read().await;
+        assert!(!map.contains_key(expired.as_ref().as_bytes()));
+        assert!(map.contains_key(unexpired.as_ref().as_bytes()));
+        drop(map);
+        assert_eq!(cache.get(&unexpired).await.unwrap(), Some(TestValA(2)));
+    }
+
     #[tokio::test]
     async fn test_cache_nx_status() {
         let cache = new();
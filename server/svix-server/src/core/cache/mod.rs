@@ -183,4 +183,20 @@ pub trait CacheBehavior: Sync + Send {
         self.set_raw_if_not_exists(key.as_ref().as_bytes(), value.to_string().as_bytes(), ttl)
             .await
     }
+
+    /// Removes the entries which have expired, for backends which don't expire them on their own
+    async fn prune_expired(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Prunes the cache's expired entries (e.g. of used single-use tokens) at the given interval, so
+/// backends without native expiry stay bounded. Spawned by the backends which need it on creation.
+pub async fn prune_expired_loop(cache: Cache, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        if let Err(err) = cache.prune_expired().await {
+            tracing::error!("Error pruning the cache: {}", err)
+        }
+    }
 }
@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: © 2022 Svix Authors
+// SPDX-License-Identifier: MIT
+
+//! Fetches and caches the public keys (JWKS) of an external identity provider, so its tokens can be
+//! verified. Keys are refreshed once the cache expires (per the response's `Cache-Control`), or when
+//! a token refers to an unknown key, as happens when the provider rotates its keys.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use jwt_simple::prelude::RS256PublicKey;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::error::{Error, Result};
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// A single key of the set. Only RSA keys are supported, others are ignored.
+#[derive(Deserialize)]
+struct Jwk {
+    kid: Option<String>,
+    kty: String,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+impl Jwk {
+    fn into_public_key(self) -> Option<(String, RS256PublicKey)> {
+        if self.kty != "RSA" {
+            return None;
+        }
+        let n = base64::decode_config(self.n?, base64::URL_SAFE_NO_PAD).ok()?;
+        let e = base64::decode_config(self.e?, base64::URL_SAFE_NO_PAD).ok()?;
+        let key = RS256PublicKey::from_components(&n, &e).ok()?;
+        Some((self.kid?, key))
+    }
+}
+
+/// How long connecting to the identity provider may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long fetching the key set may take in total
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct Inner {
+    keys: HashMap<String, Arc<RS256PublicKey>>,
+    expires_at: Option<Instant>,
+    fetched_at: Option<Instant>,
+}
+
+#[derive(Clone)]
+pub struct JwksClient {
+    url: String,
+    client: reqwest::Client,
+    /// Only ever locked briefly, never across a fetch, so lookups don't wait on the provider
+    inner: Arc<std::sync::Mutex<Inner>>,
+    /// Held for the duration of a fetch, so there's at most one in flight
+    refresh: Arc<Mutex<()>>,
+    /// How long keys are cached for when the response has no `Cache-Control: max-age`
+    default_ttl: Duration,
+    /// The minimum time between two fetches, so tokens with unknown keys can't be used to hammer
+    /// the identity provider
+    min_refresh_interval: Duration,
+}
+
+impl JwksClient {
+    pub fn new(url: String, default_ttl: Duration, min_refresh_interval: Duration) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Error initializing the JWKS client"),
+            inner: Arc::new(std::sync::Mutex::new(Inner::default())),
+            refresh: Arc::new(Mutex::new(())),
+            default_ttl,
+            min_refresh_interval,
+        }
+    }
+
+    /// The key with the given id, fetching the key set first if the cached one has expired or
+    /// doesn't have the key. When the key set can't be fetched, the previously fetched keys are
+    /// used.
+    pub async fn key(&self, kid: &str) -> Option<Arc<RS256PublicKey>> {
+        if !self.needs_refresh(kid) {
+            return self.cached(kid);
+        }
+
+        let guard = match self.refresh.clone().try_lock_owned() {
+            Ok(guard) => guard,
+            // Another lookup is already fetching the key set: use the cached key if there is one
+            // rather than waiting for it
+            Err(_) => match self.cached(kid) {
+                Some(key) => return Some(key),
+                None => self.refresh.clone().lock_owned().await,
+            },
+        };
+
+        // The key set may have been fetched while waiting for the lock
+        if self.needs_refresh(kid) {
+            // The fetch runs as its own task, so the new keys are still swapped in when the
+            // lookup waiting for them gives up
+            let client = self.clone();
+            let _ = tokio::spawn(async move {
+                client.refresh().await;
+                drop(guard);
+            })
+            .await;
+        }
+
+        self.cached(kid)
+    }
+
+    fn cached(&self, kid: &str) -> Option<Arc<RS256PublicKey>> {
+        self.inner.lock().unwrap().keys.get(kid).cloned()
+    }
+
+    fn needs_refresh(&self, kid: &str) -> bool {
+        let inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+
+        let expired = !matches!(inner.expires_at, Some(expires_at) if now < expires_at);
+        let may_refresh = !matches!(
+            inner.fetched_at,
+            Some(fetched_at) if now.duration_since(fetched_at) < self.min_refresh_interval
+        );
+        (expired || !inner.keys.contains_key(kid)) && may_refresh
+    }
+
+    async fn refresh(&self) {
+        let now = Instant::now();
+        self.inner.lock().unwrap().fetched_at = Some(now);
+        match self.fetch().await {
+            Ok((keys, ttl)) => {
+                let mut inner = self.inner.lock().unwrap();
+                inner.keys = keys;
+                inner.expires_at = Some(now + ttl);
+            }
+            Err(e) => tracing::warn!("Error fetching JWKS from {}: {}", self.url, e),
+        }
+    }
+
+    async fn fetch(&self) -> Result<(HashMap<String, Arc<RS256PublicKey>>, Duration)> {
+        let resp = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|e| Error::Generic(e.to_string()))?;
+
+        let ttl = resp
+            .headers()
+            .get(http::header::CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok())
+            .and_then(cache_control_ttl)
+            .unwrap_or(self.default_ttl);
+
+        let jwks: JwkSet = resp
+            .json()
+            .await
+            .map_err(|e| Error::Generic(e.to_string()))?;
+        let keys = jwks
+            .keys
+            .into_iter()
+            .filter_map(Jwk::into_public_key)
+            .map(|(kid, key)| (kid, Arc::new(key)))
+            .collect();
+
+        Ok((keys, ttl))
+    }
+}
+
+/// How long a response may be cached for according to its `Cache-Control` header
+fn cache_control_ttl(cache_control: &str) -> Option<Duration> {
+    cache_control
+        .split(',')
+        .map(str::trim)
+        .find_map(|directive| {
+            if directive.eq_ignore_ascii_case("no-store")
+                || directive.eq_ignore_ascii_case("no-cache")
+            {
+                Some(0)
+            } else {
+                directive.strip_prefix("max-age=")?.parse().ok()
+            }
+        })
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_control_ttl() {
+        assert_eq!(
+            cache_control_ttl("public, max-age=300"),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(cache_control_ttl("no-store"), Some(Duration::ZERO));
+        assert_eq!(cache_control_ttl("public"), None);
+        assert_eq!(cache_control_ttl("max-age=soon"), None);
+    }
+}
@@ -13,8 +13,8 @@ use crate::{
         cache::{kv_def, Cache, CacheBehavior, CacheKey, CacheValue},
         types::{
             ApplicationId, ApplicationUid, EndpointHeaders, EndpointId, EndpointSecret,
-            EventChannelSet, EventTypeNameSet, ExpiringSigningKeys, MessageAttemptTriggerType,
-            OrganizationId,
+            EventChannelSet, EventTypeName, EventTypeNameSet, ExpiringSigningKeys,
+            MessageAttemptTriggerType, OrganizationId, TlsFingerprint,
         },
     },
     db::models::{application, endpoint, message},
@@ -40,7 +40,7 @@ impl CreateMessageApp {
         db: &DatabaseTransaction,
         app: application::Model,
     ) -> Result<CreateMessageApp> {
-        let endpoints = endpoint::Entity::secure_find(app.id.clone())
+        let endpoints = endpoint::Entity::secure_find_with_draining(app.id.clone())
             .all(db)
             .await?
             .into_iter()
@@ -108,6 +108,17 @@ impl CreateMessageApp {
         &self,
         trigger_type: MessageAttemptTriggerType,
         msg: &message::Model,
+    ) -> Vec<CreateMessageEndpoint> {
+        self.filtered_endpoints_for(trigger_type, &msg.event_type, msg.channels.as_ref())
+    }
+
+    /// Like [`Self::filtered_endpoints`], but for a message with the given event type and channels,
+    /// e.g. one which isn't stored yet
+    pub fn filtered_endpoints_for(
+        &self,
+        trigger_type: MessageAttemptTriggerType,
+        event_type: &EventTypeName,
+        channels: Option<&EventChannelSet>,
     ) -> Vec<CreateMessageEndpoint> {
         self
         .endpoints
@@ -124,7 +135,7 @@ impl CreateMessageApp {
                         endpoint
                         .event_types_ids
                         .as_ref()
-                        .map(|x| x.0.contains(&msg.event_type))
+                        .map(|x| x.0.contains(event_type))
                         .unwrap_or(true)
                     &&
                         // If an endpoint has no channels accept all messages, otherwise only if their channels overlap.
@@ -132,7 +143,7 @@ impl CreateMessageApp {
                         endpoint
                         .channels
                         .as_ref()
-                        .map(|x| !x.0.is_disjoint(msg.channels.as_ref().map(|x| &x.0).unwrap_or(&HashSet::new())))
+                        .map(|x| !x.0.is_disjoint(channels.map(|x| &x.0).unwrap_or(&HashSet::new())))
                         .unwrap_or(true)
             ))})
         .cloned()
@@ -155,6 +166,9 @@ pub struct CreateMessageEndpoint {
     pub headers: Option<EndpointHeaders>,
     pub disabled: bool,
     pub deleted: bool,
+    /// Draining endpoints only receive retries of already dispatched messages
+    pub draining: bool,
+    pub pinned_tls_fingerprint: Option<TlsFingerprint>,
 }
 
 impl TryFrom<endpoint::Model> for CreateMessageEndpoint {
@@ -177,6 +191,8 @@ impl TryFrom<endpoint::Model> for CreateMessageEndpoint {
             headers: m.headers,
             disabled: m.disabled,
             deleted: m.deleted,
+            draining: m.drain_deadline.is_some(),
+            pinned_tls_fingerprint: m.pinned_tls_fingerprint,
         })
     }
 }
@@ -186,6 +202,6 @@ impl AppEndpointKey {
     // FIXME: Rewrite doc comment when AppEndpointValue members are known
     /// Returns a key for fetching all cached endpoints for a given organization and application.
     pub fn new(org: OrganizationId, app: ApplicationId) -> AppEndpointKey {
-        AppEndpointKey(format!("{}_APP_v3_{}_{}", Self::PREFIX_CACHE, org, app))
+        AppEndpointKey(format!("{}_APP_v5_{}_{}", Self::PREFIX_CACHE, org, app))
     }
 }
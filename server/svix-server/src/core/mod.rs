@@ -1,13 +1,19 @@
 // SPDX-FileCopyrightText: © 2022 Svix Authors
 // SPDX-License-Identifier: MIT
 
+pub mod auth_audit;
 pub mod cache;
 pub mod idempotency;
+pub mod jwks;
 pub mod message_app;
 pub mod operational_webhooks;
 pub mod otel_spans;
 pub mod security;
+pub mod tls_pinning;
+pub mod token_binding;
+pub mod token_cache;
 pub mod types;
+pub mod webhook_signature;
 
 #[cfg(test)]
 mod tests {
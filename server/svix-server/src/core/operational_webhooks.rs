@@ -13,8 +13,8 @@ use svix::api::{MessageIn, Svix, SvixOptions};
 use super::{
     security::{generate_management_token, Keys},
     types::{
-        ApplicationId, ApplicationUid, EndpointId, EndpointUid, MessageAttemptId, MessageId,
-        MessageUid, OrganizationId,
+        ApplicationId, ApplicationUid, EndpointId, EndpointUid, EventTypeName, MessageAttemptId,
+        MessageId, MessageUid, OrganizationId, TlsFingerprint,
     },
 };
 use crate::{
@@ -43,6 +43,28 @@ pub struct EndpointEvent<'a> {
     pub endpoint_uid: Option<&'a EndpointUid>,
 }
 
+/// Sent when an endpoint presents a different TLS certificate than on its previous delivery
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointTlsFingerprintChangedEvent<'a> {
+    pub app_id: &'a ApplicationId,
+    pub app_uid: Option<&'a ApplicationUid>,
+    pub endpoint_id: &'a EndpointId,
+    pub previous_fingerprint: &'a TlsFingerprint,
+    pub fingerprint: &'a TlsFingerprint,
+}
+
+/// Sent when a message is created which matches none of its application's endpoints
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageNoMatchingEndpointsEvent<'a> {
+    pub app_id: &'a ApplicationId,
+    pub app_uid: Option<&'a ApplicationUid>,
+    pub msg_id: &'a MessageId,
+    pub msg_event_id: Option<&'a MessageUid>,
+    pub event_type: &'a EventTypeName,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MessageAttempetLast<'a> {
@@ -86,22 +108,31 @@ pub enum OperationalWebhook<'a> {
     EndpointUpdated(EndpointEvent<'a>),
     #[serde(rename = "endpoint.deleted")]
     EndpointDeleted(EndpointEvent<'a>),
+    #[serde(rename = "endpoint.tls_fingerprint_changed")]
+    EndpointTlsFingerprintChanged(EndpointTlsFingerprintChangedEvent<'a>),
     #[serde(rename = "message.attempt.exhausted")]
     MessageAttemptExhausted(MessageAttemptEvent<'a>),
     #[serde(rename = "message.attempt.failing")]
     MessageAttemptFailing(MessageAttemptEvent<'a>),
+    #[serde(rename = "message.no_matching_endpoints")]
+    MessageNoMatchingEndpoints(MessageNoMatchingEndpointsEvent<'a>),
 }
 
 pub type OperationalWebhookSender = Arc<OperationalWebhookSenderInner>;
 
 pub struct OperationalWebhookSenderInner {
     keys: Keys,
+    management_org_id: OrganizationId,
     url: Option<String>,
 }
 
 impl OperationalWebhookSenderInner {
-    pub fn new(keys: Keys, url: Option<String>) -> Arc<Self> {
-        Arc::new(Self { keys, url })
+    pub fn new(keys: Keys, management_org_id: OrganizationId, url: Option<String>) -> Arc<Self> {
+        Arc::new(Self {
+            keys,
+            management_org_id,
+            url,
+        })
     }
 
     pub async fn send_operational_webhook(
@@ -115,7 +146,8 @@ impl OperationalWebhookSenderInner {
         };
 
         let op_webhook_token =
-            generate_management_token(&self.keys).expect("Error generating Svix Management token");
+            generate_management_token(&self.keys, self.management_org_id.clone())
+                .expect("Error generating Svix Management token");
         let svix_api = Svix::new(
             op_webhook_token,
             Some(SvixOptions {
@@ -1,16 +1,26 @@
 // SPDX-FileCopyrightText: © 2022 Svix Authors
 // SPDX-License-Identifier: MIT
 
-use std::fmt::Display;
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 use axum::{
     async_trait,
-    extract::{Extension, FromRequest, Path, RequestParts, TypedHeader},
+    extract::{ConnectInfo, Extension, FromRequest, OriginalUri, Path, RequestParts, TypedHeader},
     headers::{authorization::Bearer, Authorization},
 };
+use chrono::{DateTime, Utc};
 
-use jwt_simple::prelude::*;
-use sea_orm::DatabaseConnection;
+use jwt_simple::{prelude::*, JWTError};
+use rand::Rng;
+use sea_orm::{ColumnTrait, DatabaseConnection, QueryFilter};
+use serde::de::DeserializeOwned;
+use subtle::ConstantTimeEq;
+use tracing::{field::Empty, Instrument};
 use validator::Validate;
 
 use crate::{
@@ -19,273 +29,3977 @@ use crate::{
     error::{Error, HttpError, Result},
 };
 
-use super::types::{ApplicationId, ApplicationIdOrUid, OrganizationId};
+use super::{
+    auth_audit::{AuthAuditEvent, AuthAuditOutcome, SharedAuthAuditSink},
+    cache::{kv_def, Cache, CacheBehavior, CacheKey, CacheValue},
+    jwks::JwksClient,
+    token_binding::{client_ip, IpRange},
+    token_cache::TokenCache,
+    types::{ApplicationId, ApplicationIdOrUid, MessageId, OrganizationId},
+};
 
 /// The default org_id we use (useful for generating JWTs when testing).
 pub fn default_org_id() -> OrganizationId {
     OrganizationId("org_23rb8YdGqMT0qIzpgGwdXfHirMu".to_owned())
 }
 
-/// The default org_id we use (useful for generating JWTs when testing).
-pub fn management_org_id() -> OrganizationId {
-    OrganizationId("org_00000000000SvixManagement00".to_owned())
-}
-
 fn to_internal_server_error(x: impl Display) -> HttpError {
     tracing::error!("Error: {}", x);
     HttpError::internal_server_errer(None, None)
 }
 
+#[derive(Clone)]
 pub struct Permissions {
     pub type_: KeyType,
     pub org_id: OrganizationId,
     pub app_id: Option<ApplicationId>,
+    /// Further organizations an organization token may access, given through the `orgs` claim
+    pub additional_org_ids: HashSet<OrganizationId>,
+    /// What the token may be used for, given through the `scope` claim
+    pub scopes: HashSet<String>,
+    /// Beta features the token has access to, given through the `features` claim
+    pub features: HashSet<String>,
+    /// The environment (e.g. `live` or `sandbox`) the token is for, given through the `env` claim
+    pub env: Option<String>,
+    /// The single message the token is limited to reading, given through the `msg` claim
+    pub message_id: Option<MessageId>,
+    /// The origins browsers may use the token from, given through the `origins` claim
+    pub allowed_origins: Option<Vec<String>>,
+    /// The human-readable label of the token, given through the `label` claim. It's only for
+    /// auditing, and never grants or denies access.
+    pub label: Option<String>,
+    /// The addresses the token may be used from, given through the `cnf` claim
+    pub bound_ip_range: Option<IpRange>,
+    /// The claims of the token the permissions were verified from, e.g. for its `iat` and `exp`
+    pub claims: Option<Arc<JWTClaims<CustomClaim>>>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+impl Permissions {
+    pub fn org_id(&self) -> &OrganizationId {
+        &self.org_id
+    }
+
+    pub fn app_id(&self) -> Option<&ApplicationId> {
+        self.app_id.as_ref()
+    }
+
+    /// Whether the token grants access to the given organization
+    pub fn has_org(&self, org_id: &OrganizationId) -> bool {
+        &self.org_id == org_id || self.additional_org_ids.contains(org_id)
+    }
+
+    /// Whether the token grants access to the given beta feature
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.contains(feature)
+    }
+
+    /// Fails with `not_found` unless the token grants access to the given beta feature, so beta
+    /// endpoints stay hidden from everyone else
+    pub fn require_feature(&self, feature: &str) -> Result<()> {
+        if self.has_feature(feature) {
+            Ok(())
+        } else {
+            Err(HttpError::not_found(None, None).into())
+        }
+    }
+
+    /// Whether the token grants access to the given message, which it does unless it's limited to
+    /// a single other message
+    pub fn allows_message(&self, msg_id: &MessageId) -> bool {
+        self.message_id.is_none() || self.message_id.as_ref() == Some(msg_id)
+    }
+
+    /// The environment the token is for, which is the `default_environment` for tokens without an
+    /// `env` claim
+    pub fn env<'a>(&'a self, cfg: &'a Configuration) -> &'a str {
+        self.env.as_deref().unwrap_or(&cfg.default_environment)
+    }
+
+    /// Fails unless the token is for the given environment, so e.g. a live token can't touch
+    /// sandbox data
+    pub fn require_env(
+        &self,
+        cfg: &Configuration,
+        env: &str,
+    ) -> std::result::Result<(), AuthError> {
+        if self.env(cfg) == env {
+            Ok(())
+        } else {
+            Err(AuthError::WrongEnvironment(env.to_owned()))
+        }
+    }
+
+    /// Describes what the token these permissions were verified from grants access to. It never
+    /// includes the token itself.
+    pub fn describe(&self, claims: &JWTClaims<CustomClaim>) -> TokenInfo {
+        let mut additional_org_ids: Vec<_> = self.additional_org_ids.iter().cloned().collect();
+        additional_org_ids.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut scopes: Vec<_> = self.scopes.iter().cloned().collect();
+        scopes.sort();
+        let mut features: Vec<_> = self.features.iter().cloned().collect();
+        features.sort();
+
+        TokenInfo {
+            type_: self.type_,
+            org_id: self.org_id.clone(),
+            app_id: self.app_id.clone(),
+            additional_org_ids,
+            scopes,
+            features,
+            env: self.env.clone(),
+            message_id: self.message_id.clone(),
+            allowed_origins: self.allowed_origins.clone(),
+            label: self.label.clone(),
+            expires_at: claims.expires_at.map(|exp| {
+                DateTime::<Utc>::from(
+                    std::time::UNIX_EPOCH + std::time::Duration::from_secs(exp.as_secs()),
+                )
+            }),
+        }
+    }
+}
+
+/// The introspection output of [`Permissions::describe`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenInfo {
+    #[serde(rename = "type")]
+    pub type_: KeyType,
+    pub org_id: OrganizationId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<ApplicationId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additional_org_ids: Vec<OrganizationId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<MessageId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_origins: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Formats the permissions for logs and audit records as `org=...,type=...,app=...` (`app` only
+/// for application tokens). It only includes the ids, never anything secret.
+impl Display for Permissions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let type_ = match self.type_ {
+            KeyType::Organization => "organization",
+            KeyType::Application => "application",
+        };
+        write!(f, "org={},type={}", self.org_id, type_)?;
+        if let Some(app_id) = &self.app_id {
+            write!(f, ",app={}", app_id)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum KeyType {
     Organization,
     Application,
 }
 
+impl KeyType {
+    fn as_str(self) -> &'static str {
+        match self {
+            KeyType::Organization => "organization",
+            KeyType::Application => "application",
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct CustomClaim {
     #[serde(rename = "org", default, skip_serializing_if = "Option::is_none")]
     organization: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    orgs: Option<Vec<String>>,
+    /// Whether the token may only be used once, as identified by its `jti`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    single_use: bool,
+    /// What an application token may be used for, as space-separated scopes (RFC 8693)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    /// Beta features the token has access to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    features: Option<Vec<String>>,
+    /// The environment the token is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    env: Option<String>,
+    /// The single message the token is limited to reading
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    msg: Option<String>,
+    /// The origins (e.g. `https://dashboard.example.com`) browsers may use the token from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origins: Option<Vec<String>>,
+    /// A human-readable label (e.g. the integration it was minted for) to tell tokens apart in
+    /// audit records. It's never used for authorization.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    /// The type of token it was minted as, which has to match the type implied by the other claims
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<TokenKind>,
+    /// The version of the claims' schema, so servers reject tokens with claims they don't know of
+    /// rather than ignore them. Tokens minted before it was introduced are version 1.
+    #[serde(default = "default_claims_version")]
+    v: u32,
+    /// Binds the token to the addresses it may be used from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cnf: Option<Confirmation>,
 }
 
-#[async_trait]
-impl<B> FromRequest<B> for Permissions
-where
-    B: Send,
-{
-    type Rejection = Error;
-
-    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
-        let Extension(ref cfg) = Extension::<Configuration>::from_request(req)
-            .await
-            .map_err(to_internal_server_error)?;
+/// The version of the claims' schema this server understands, and mints tokens with
+const CLAIMS_VERSION: u32 = 1;
 
-        let TypedHeader(Authorization(bearer)) =
-            TypedHeader::<Authorization<Bearer>>::from_request(req)
-                .await
-                .map_err(|_| HttpError::unauthorized(None, Some("Invalid token".to_string())))?;
+/// The maximum length (in characters) of the `label` claim, to keep tokens small
+pub const MAX_TOKEN_LABEL_LENGTH: usize = 64;
 
-        let claims = cfg
-            .jwt_secret
-            .key
-            .verify_token::<CustomClaim>(bearer.token(), None)
-            .map_err(|_| HttpError::unauthorized(None, Some("Invalid token".to_string())))?;
+fn is_valid_token_label(label: &str) -> bool {
+    !label.is_empty() && label.chars().count() <= MAX_TOKEN_LABEL_LENGTH
+}
 
-        let bad_token = |field: &str, id_type: &str| {
-            HttpError::bad_request(
-                Some("bad token".to_string()),
-                Some(format!("`{}` is not a valid {} id", field, id_type)),
-            )
-        };
+fn default_claims_version() -> u32 {
+    1
+}
 
-        // If there is an `org` field then it is an Application authentication
-        if let Some(org_id) = claims.custom.organization {
-            let org_id = OrganizationId(org_id);
-            org_id
-                .validate()
-                .map_err(|_| bad_token("org", "organization"))?;
-
-            if let Some(app_id) = claims.subject {
-                let app_id = ApplicationId(app_id);
-                app_id
-                    .validate()
-                    .map_err(|_| bad_token("sub", "application"))?;
-
-                Ok(Permissions {
-                    org_id,
-                    app_id: Some(app_id),
-                    type_: KeyType::Application,
-                })
-            } else {
-                Err(HttpError::unauthorized(
-                    None,
-                    Some("Invalid token (missing `sub`).".to_string()),
-                )
-                .into())
-            }
-        }
-        // Otherwsie it's an Organization authentication
-        else if let Some(org_id) = claims.subject {
-            let org_id = OrganizationId(org_id);
-            org_id.validate().map_err(|_| {
-                HttpError::bad_request(
-                    Some("bad_token".to_string()),
-                    Some("`sub' is not a valid organization id.".to_string()),
-                )
-            })?;
-            Ok(Permissions {
-                org_id,
-                app_id: None,
-                type_: KeyType::Organization,
-            })
-        } else {
-            Err(
-                HttpError::unauthorized(None, Some("Invalid token (missing `sub`).".to_string()))
-                    .into(),
-            )
+/// The claims of a token of the current version without any restrictions, which minting fills in
+impl Default for CustomClaim {
+    fn default() -> Self {
+        CustomClaim {
+            organization: None,
+            orgs: None,
+            single_use: false,
+            scope: None,
+            features: None,
+            env: None,
+            msg: None,
+            origins: None,
+            label: None,
+            kind: None,
+            v: CLAIMS_VERSION,
+            cnf: None,
         }
     }
 }
 
-pub struct AuthenticatedOrganization {
-    pub permissions: Permissions,
+/// The `kind` claim, stamped on tokens when they're minted so an organization token can't pass as
+/// an application token or vice versa
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TokenKind {
+    Org,
+    #[serde(rename = "app")]
+    Application,
+    #[serde(rename = "mgmt")]
+    Management,
+}
+
+/// The `cnf` (confirmation) claim, with the IP address or CIDR range a token may be used from
+#[derive(Clone, Serialize, Deserialize)]
+struct Confirmation {
+    ip: String,
 }
 
 #[async_trait]
-impl<B> FromRequest<B> for AuthenticatedOrganization
+impl<B> FromRequest<B> for Permissions
 where
     B: Send,
 {
     type Rejection = Error;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
-        let permissions = Permissions::from_request(req).await?;
-        match permissions.type_ {
-            KeyType::Organization => {}
-            KeyType::Application => {
-                return Err(HttpError::permission_denied(None, None).into());
+        let span = tracing::info_span!(
+            "authenticate",
+            permissions = Empty,
+            key_type = Empty,
+            org_id = Empty,
+            outcome = Empty,
+            reason = Empty,
+        );
+
+        // Only the outcome and the (non-secret) ids are recorded, never the token itself
+        let result = Permissions::authenticate(req)
+            .instrument(span.clone())
+            .await;
+        let audit_event = match &result {
+            Ok(permissions) => {
+                span.record("permissions", &tracing::field::display(permissions));
+                span.record("key_type", &permissions.type_.as_str());
+                span.record("org_id", &permissions.org_id().0.as_str());
+                span.record("outcome", &"success");
+                tracing::info!(parent: &span, monotonic_counter.auth_success_total = 1_u64);
+                AuthAuditEvent {
+                    org_id: Some(permissions.org_id().clone()),
+                    key_type: Some(permissions.type_),
+                    label: permissions.label.clone(),
+                    outcome: AuthAuditOutcome::Success,
+                    timestamp: Utc::now(),
+                }
             }
+            Err(err) => {
+                span.record("outcome", &"failure");
+                span.record("reason", &err.reason());
+                tracing::info!(
+                    parent: &span,
+                    monotonic_counter.auth_failure_total = 1_u64,
+                    reason = err.reason()
+                );
+                AuthAuditEvent {
+                    org_id: None,
+                    key_type: None,
+                    label: None,
+                    outcome: AuthAuditOutcome::Failure {
+                        reason: err.reason(),
+                    },
+                    timestamp: Utc::now(),
+                }
+            }
+        };
+
+        if let Ok(Extension(audit_sink)) = Extension::<SharedAuthAuditSink>::from_request(req).await
+        {
+            tokio::spawn(async move { audit_sink.record(audit_event).await });
         }
 
-        Ok(AuthenticatedOrganization { permissions })
+        let permissions = result.map_err(Error::from)?;
+        check_message_path(req, &permissions).await?;
+        Ok(permissions)
     }
 }
 
-#[derive(Deserialize)]
-struct ApplicationPathParams {
-    app_id: ApplicationIdOrUid,
+/// Why authenticating a request failed. The [`Permissions`] extractor turns it into the
+/// [`HttpError`] sent to the client, while internal callers can branch on it through
+/// [`Permissions::authenticate`].
+#[derive(Clone, Debug)]
+pub enum AuthError {
+    /// There's no `Authorization: Bearer` header
+    MissingHeader,
+    /// The `Authorization` header is longer than `jwt_max_header_length`
+    HeaderTooLarge,
+    /// The `Authorization` header uses another scheme than `Bearer` (e.g. `Basic`), or none at all
+    UnsupportedScheme,
+    /// The token isn't even shaped like a JWT, so it wasn't verified at all
+    MalformedToken,
+    /// Verifying the token took longer than `jwt_verification_timeout`
+    Timeout,
+    /// The token has expired
+    Expired,
+    /// The token's `nbf` is still in the future
+    NotYetValid,
+    /// The token can't be verified with the server's key, e.g. as its signature is wrong or it
+    /// isn't a JWT at all
+    BadSignature,
+    /// The token wasn't issued by this server or one of the `jwt_allowed_issuers`
+    BadIssuer,
+    /// The token was minted as another type of token than its claims make it, e.g. as an
+    /// organization token but with an application's claims
+    KindMismatch,
+    /// The given claim is missing
+    MissingClaim(&'static str),
+    /// The given claim doesn't hold what it should, e.g. `sub` isn't a valid organization id
+    MalformedClaim(&'static str, ClaimKind),
+    /// The token is valid, but can't be used from the client's address
+    Forbidden,
+    /// The token is valid, but can't be used from the request's `Origin`, or without one
+    OriginMismatch,
+    /// The token is valid, but for another environment than the given one the server serves
+    WrongEnvironment(String),
+    /// The single-use token has already been used
+    AlreadyUsed,
+    /// The client had too many authentication failures, so it's blocked for a while
+    RateLimited,
+    /// The token's claims are of a newer version than this server understands
+    UnsupportedVersion,
+    /// Authentication couldn't be completed, e.g. as the cache is unavailable
+    Internal(Error),
 }
 
-pub struct AuthenticatedOrganizationWithApplication {
-    pub permissions: Permissions,
-    pub app: application::Model,
+/// What a claim should hold, for telling the client what's wrong with a [`AuthError::MalformedClaim`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClaimKind {
+    OrganizationId,
+    ApplicationId,
+    MessageId,
+    /// An IP address or CIDR range
+    IpRange,
+    Label,
 }
 
-#[async_trait]
-impl<B> FromRequest<B> for AuthenticatedOrganizationWithApplication
-where
-    B: Send,
-{
-    type Rejection = Error;
+impl AuthError {
+    /// Whether the failure could come from guessing tokens, so it counts towards blocking the
+    /// client. Valid tokens used from the wrong address, or failures of the server, don't.
+    fn counts_towards_limit(&self) -> bool {
+        !matches!(
+            self,
+            AuthError::MissingHeader
+                | AuthError::Forbidden
+                | AuthError::OriginMismatch
+                | AuthError::WrongEnvironment(_)
+                | AuthError::Timeout
+                | AuthError::RateLimited
+                | AuthError::Internal(_)
+        )
+    }
 
-    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
-        let permissions = Permissions::from_request(req).await?;
+    /// The reason recorded in the `authenticate` span and the `auth_failure_total` counter
+    fn reason(&self) -> &'static str {
+        match self {
+            AuthError::MissingHeader => "missing_token",
+            AuthError::HeaderTooLarge => "header_too_large",
+            AuthError::UnsupportedScheme => "unsupported_auth_scheme",
+            AuthError::Timeout => "timeout",
+            AuthError::Expired => "expired_token",
+            AuthError::NotYetValid => "token_not_yet_valid",
+            AuthError::MalformedToken
+            | AuthError::BadSignature
+            | AuthError::BadIssuer
+            | AuthError::KindMismatch => "invalid_token",
+            AuthError::MissingClaim(_) | AuthError::MalformedClaim(..) => "invalid_claims",
+            AuthError::Forbidden => "address_mismatch",
+            AuthError::OriginMismatch => "origin_mismatch",
+            AuthError::WrongEnvironment(_) => "environment_mismatch",
+            AuthError::AlreadyUsed => "token_already_used",
+            AuthError::RateLimited => "rate_limited",
+            AuthError::UnsupportedVersion => "unsupported_claims_version",
+            AuthError::Internal(_) => "internal",
+        }
+    }
+}
 
-        match permissions.type_ {
-            KeyType::Organization => {}
-            KeyType::Application => {
-                return Err(HttpError::permission_denied(None, None).into());
+impl From<AuthError> for Error {
+    fn from(err: AuthError) -> Error {
+        match err {
+            // Requests without any credentials get a bare challenge (RFC 6750, section 3.1)
+            AuthError::MissingHeader => {
+                HttpError::unauthorized(None, Some("Invalid token".to_string()))
+                    .with_bare_auth_challenge()
+                    .into()
+            }
+            AuthError::MalformedToken
+            | AuthError::BadSignature
+            | AuthError::BadIssuer
+            | AuthError::KindMismatch => {
+                HttpError::unauthorized(None, Some("Invalid token".to_string())).into()
+            }
+            AuthError::HeaderTooLarge => HttpError::bad_request(
+                Some("header_too_large".to_string()),
+                Some("The `Authorization` header is too large.".to_string()),
+            )
+            .into(),
+            AuthError::UnsupportedScheme => HttpError::unauthorized(
+                Some("unsupported_auth_scheme".to_string()),
+                Some("The `Authorization` header must use the `Bearer` scheme.".to_string()),
+            )
+            .with_bare_auth_challenge()
+            .into(),
+            AuthError::Timeout => HttpError::service_unavailable(None, None).into(),
+            AuthError::Expired => HttpError::unauthorized(None, Some("Token expired".to_string()))
+                .with_auth_error("expired_token")
+                .into(),
+            AuthError::NotYetValid => {
+                HttpError::unauthorized(None, Some("Token not yet valid".to_string()))
+                    .with_auth_error("token_not_yet_valid")
+                    .into()
+            }
+            AuthError::MissingClaim(claim) => {
+                HttpError::unauthorized(None, Some(format!("Invalid token (missing `{}`).", claim)))
+                    .into()
             }
+            // The `sub` of organization tokens has always been reported slightly differently
+            AuthError::MalformedClaim("sub", ClaimKind::OrganizationId) => HttpError::bad_request(
+                Some("bad_token".to_string()),
+                Some("`sub' is not a valid organization id.".to_string()),
+            )
+            .into(),
+            AuthError::MalformedClaim(claim, kind) => {
+                let detail = match kind {
+                    ClaimKind::OrganizationId => {
+                        format!("`{}` is not a valid organization id", claim)
+                    }
+                    ClaimKind::ApplicationId => {
+                        format!("`{}` is not a valid application id", claim)
+                    }
+                    ClaimKind::MessageId => format!("`{}` is not a valid message id", claim),
+                    ClaimKind::IpRange => {
+                        format!("`{}` is not a valid IP address or range", claim)
+                    }
+                    ClaimKind::Label => format!(
+                        "`{}` must be between 1 and {} characters long",
+                        claim, MAX_TOKEN_LABEL_LENGTH
+                    ),
+                };
+                HttpError::bad_request(Some("bad token".to_string()), Some(detail)).into()
+            }
+            AuthError::Forbidden => HttpError::permission_denied(
+                None,
+                Some("This token can't be used from this address.".to_string()),
+            )
+            .into(),
+            AuthError::OriginMismatch => HttpError::permission_denied(
+                None,
+                Some("This token can't be used from this origin.".to_string()),
+            )
+            .into(),
+            AuthError::WrongEnvironment(env) => HttpError::permission_denied(
+                None,
+                Some(format!(
+                    "This token can't be used in the `{}` environment.",
+                    env
+                )),
+            )
+            .into(),
+            AuthError::AlreadyUsed => HttpError::unauthorized(
+                Some("token_already_used".to_string()),
+                Some("This single-use token has already been used.".to_string()),
+            )
+            .into(),
+            AuthError::UnsupportedVersion => HttpError::unauthorized(
+                Some("unsupported_claims_version".to_string()),
+                Some("This token was issued for a newer version of the server.".to_string()),
+            )
+            .into(),
+            AuthError::RateLimited => HttpError::too_many_requests(
+                None,
+                Some("Too many failed authentication attempts, try again later.".to_string()),
+            )
+            .into(),
+            AuthError::Internal(err) => err,
         }
+    }
+}
 
-        let Path(ApplicationPathParams { app_id }) =
-            Path::<ApplicationPathParams>::from_request(req)
-                .await
-                .map_err(to_internal_server_error)?;
-        let Extension(ref db) = Extension::<DatabaseConnection>::from_request(req)
+impl Permissions {
+    /// Authenticates the request like the extractor, but without recording the outcome, and with
+    /// a structured error for callers which need to know why it failed
+    pub async fn authenticate<B: Send>(
+        req: &mut RequestParts<B>,
+    ) -> std::result::Result<Self, AuthError> {
+        let Extension(ref cfg) = Extension::<Configuration>::from_request(req)
             .await
-            .map_err(to_internal_server_error)?;
-        let app = application::Entity::secure_find_by_id_or_uid(
-            permissions.org_id.clone(),
-            app_id.to_owned(),
-        )
-        .one(db)
-        .await?
-        .ok_or_else(|| HttpError::not_found(None, None))?;
-        Ok(AuthenticatedOrganizationWithApplication { permissions, app })
+            .map_err(|e| AuthError::Internal(to_internal_server_error(e).into()))?;
+
+        let limiter = AuthFailureLimiter::new(req, cfg).await;
+        if let Some(limiter) = &limiter {
+            limiter.check()?;
+        }
+        let result = Permissions::authenticate_with(req, cfg).await;
+        if let Some(limiter) = limiter {
+            limiter.record(cfg, &result).await;
+        }
+        result
     }
-}
 
-pub struct AuthenticatedApplication {
-    pub permissions: Permissions,
-    pub app: application::Model,
-}
+    async fn authenticate_with<B: Send>(
+        req: &mut RequestParts<B>,
+        cfg: &Configuration,
+    ) -> std::result::Result<Self, AuthError> {
+        // Checked on every request, as cached tokens may be used from anywhere
+        let permissions = Permissions::authenticate_token(req, cfg).await?;
+        if cfg.require_app_token_scope
+            && permissions.type_ == KeyType::Application
+            && permissions.scopes.is_empty()
+        {
+            return Err(AuthError::MissingClaim("scope"));
+        }
 
-#[async_trait]
-impl<B> FromRequest<B> for AuthenticatedApplication
-where
-    B: Send,
-{
-    type Rejection = Error;
+        if let Some(bound_ip_range) = &permissions.bound_ip_range {
+            let ip = client_ip(
+                req.headers(),
+                req.extensions().get::<ConnectInfo<SocketAddr>>(),
+                cfg.trust_forwarded_for,
+            );
+            if !matches!(ip, Some(ip) if bound_ip_range.contains(ip)) {
+                return Err(AuthError::Forbidden);
+            }
+        }
 
-    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
-        let permissions = Permissions::from_request(req).await?;
-        let Path(ApplicationPathParams { app_id }) =
-            Path::<ApplicationPathParams>::from_request(req)
+        // Browsers always send the `Origin` of cross-origin requests, so a token limited to some
+        // origins is rejected without one
+        if let Some(allowed_origins) = &permissions.allowed_origins {
+            let allowed = req
+                .headers()
+                .get(http::header::ORIGIN)
+                .and_then(|origin| origin.to_str().ok())
+                .into_iter()
+                .any(|origin| {
+                    allowed_origins
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+                });
+            if !allowed {
+                return Err(AuthError::OriginMismatch);
+            }
+        }
+
+        if let Some(env) = &cfg.environment {
+            permissions.require_env(cfg, env)?;
+        }
+
+        // Only used up once every other check passed, so a request rejected for another reason
+        // doesn't burn the token
+        if let Some(claims) = permissions
+            .claims
+            .as_ref()
+            .filter(|claims| claims.custom.single_use)
+        {
+            let jti = claims
+                .jwt_id
+                .as_deref()
+                .ok_or(AuthError::MissingClaim("jti"))?;
+            let expires_at = claims.expires_at.ok_or(AuthError::MissingClaim("exp"))?;
+            mark_token_used(req, cfg, jti, expires_at).await?;
+        }
+        Ok(permissions)
+    }
+
+    async fn authenticate_token<B: Send>(
+        req: &mut RequestParts<B>,
+        cfg: &Configuration,
+    ) -> std::result::Result<Self, AuthError> {
+        // Rejected before parsing, so huge headers can't tie up the server
+        if matches!(
+            req.headers().get(http::header::AUTHORIZATION),
+            Some(header) if header.len() > cfg.jwt_max_header_length
+        ) {
+            return Err(AuthError::HeaderTooLarge);
+        }
+
+        let TypedHeader(Authorization(bearer)) =
+            TypedHeader::<Authorization<Bearer>>::from_request(req)
                 .await
-                .map_err(to_internal_server_error)?;
-        let Extension(ref db) = Extension::<DatabaseConnection>::from_request(req)
+                .map_err(|_| authorization_error(req.headers()))?;
+
+        if !is_well_formed_token(bearer.token()) {
+            return Err(AuthError::MalformedToken);
+        }
+
+        // The cache is optional, so the extractor keeps working wherever it isn't set up
+        let token_cache = Extension::<TokenCache>::from_request(req)
             .await
-            .map_err(to_internal_server_error)?;
-        let app = application::Entity::secure_find_by_id_or_uid(
-            permissions.org_id.clone(),
-            app_id.to_owned(),
+            .ok()
+            .map(|Extension(token_cache)| token_cache);
+        if let Some(permissions) = token_cache.as_ref().and_then(|c| c.get(bearer.token())) {
+            return Ok(permissions);
+        }
+
+        // Tokens of the identity provider refer to one of its keys, which this server's own tokens
+        // never do
+        if let Ok(Extension(Some(jwks))) = Extension::<Option<JwksClient>>::from_request(req).await
+        {
+            let kid = Token::decode_metadata(bearer.token())
+                .ok()
+                .and_then(|metadata| metadata.key_id().map(ToOwned::to_owned));
+            if let Some(kid) = kid {
+                let (permissions, expires_at) =
+                    authenticate_idp_token(&jwks, cfg, bearer.token(), &kid).await?;
+                if let Some(token_cache) = token_cache {
+                    token_cache.insert(bearer.token(), permissions.clone(), Some(expires_at));
+                }
+                return Ok(permissions);
+            }
+        }
+
+        let claims = verify_with_timeout(
+            std::time::Duration::from_millis(cfg.jwt_verification_timeout),
+            {
+                let cfg = cfg.clone();
+                let token = bearer.token().to_owned();
+                move || {
+                    cfg.jwt_secret
+                        .key
+                        .verify_token::<CustomClaim>(&token, Some(verification_options(&cfg)))
+                }
+            },
         )
-        .one(db)
         .await?
-        .ok_or_else(|| HttpError::not_found(None, None))?;
+        .map_err(verification_error)?;
+        // Tokens without an expiry would otherwise never expire
+        let expires_at = claims.expires_at.ok_or(AuthError::MissingClaim("exp"))?;
 
-        if let Some(permitted_app_id) = &permissions.app_id {
-            if permitted_app_id != &app.id {
-                return Err(HttpError::not_found(None, None).into());
+        let single_use = claims.custom.single_use;
+        if single_use && claims.jwt_id.is_none() {
+            return Err(AuthError::MissingClaim("jti"));
+        }
+
+        let kind = claims.custom.kind;
+        let permissions = Permissions::from_claims(claims)?;
+        check_token_kind(kind, &permissions, cfg)?;
+
+        // Single-use tokens are never cached, as every use has to be checked
+        if !single_use {
+            if let Some(token_cache) = token_cache {
+                token_cache.insert(bearer.token(), permissions.clone(), Some(expires_at));
             }
         }
+        Ok(permissions)
+    }
+}
+
+/// Checks that the token was minted as the type of token its claims make it. Tokens minted before
+/// the `kind` claim was introduced are accepted unless `require_token_kind` is set.
+fn check_token_kind(
+    kind: Option<TokenKind>,
+    permissions: &Permissions,
+    cfg: &Configuration,
+) -> std::result::Result<(), AuthError> {
+    match (kind, permissions.type_) {
+        (None, _) if cfg.require_token_kind => Err(AuthError::MissingClaim("kind")),
+        (None, _)
+        | (Some(TokenKind::Application), KeyType::Application)
+        | (Some(TokenKind::Org), KeyType::Organization) => Ok(()),
+        (Some(TokenKind::Management), KeyType::Organization)
+            if permissions.org_id == cfg.management_org_id =>
+        {
+            Ok(())
+        }
+        _ => Err(AuthError::KindMismatch),
+    }
+}
 
-        Ok(AuthenticatedApplication { permissions, app })
+/// Why the `Authorization` header couldn't be parsed as a bearer token, so clients using another
+/// scheme are told so rather than that their token is invalid
+fn authorization_error(headers: &http::HeaderMap) -> AuthError {
+    let header = match headers.get(http::header::AUTHORIZATION) {
+        Some(header) => header.as_bytes(),
+        None => return AuthError::MissingHeader,
+    };
+    let scheme = header.split(|&b| b == b' ').next().unwrap_or_default();
+    if scheme.eq_ignore_ascii_case(b"Bearer") {
+        AuthError::MalformedToken
+    } else {
+        AuthError::UnsupportedScheme
     }
 }
 
-const JWT_ISSUER: &str = env!("CARGO_PKG_NAME");
+/// Bounds on the length of a token, outside of which it's rejected without being verified
+const MIN_TOKEN_LENGTH: usize = 20;
+const MAX_TOKEN_LENGTH: usize = 8 * 1024;
 
-pub fn generate_org_token(keys: &Keys, org_id: OrganizationId) -> Result<String> {
-    let claims = Claims::with_custom_claims(
-        CustomClaim { organization: None },
-        Duration::from_hours(24 * 365 * 10),
-    )
-    .with_issuer(JWT_ISSUER)
-    .with_subject(org_id.0);
-    Ok(keys.key.authenticate(claims).unwrap())
+/// Whether the token is shaped like a JWT: three non-empty base64url segments separated by dots.
+/// It's a cheap check to spare verifying garbage, and looks at every byte regardless of where the
+/// token goes wrong, so it doesn't tell near-valid tokens from far-invalid ones by its timing.
+fn is_well_formed_token(token: &str) -> bool {
+    let bytes = token.as_bytes();
+    if !(MIN_TOKEN_LENGTH..=MAX_TOKEN_LENGTH).contains(&bytes.len()) {
+        return false;
+    }
+
+    let mut dots = 0_usize;
+    let mut invalid = false;
+    let mut empty_segment = false;
+    // The start of the token counts as a separator, so a leading dot is an empty segment
+    let mut after_dot = true;
+    for &b in bytes {
+        let is_dot = b == b'.';
+        let is_base64url = b.is_ascii_alphanumeric() | (b == b'-') | (b == b'_');
+        invalid |= !(is_dot | is_base64url);
+        empty_segment |= is_dot & after_dot;
+        dots += usize::from(is_dot);
+        after_dot = is_dot;
+    }
+    empty_segment |= after_dot;
+
+    (dots == 2) & !invalid & !empty_segment
 }
 
-pub fn generate_management_token(keys: &Keys) -> Result<String> {
-    let claims =
-        Claims::with_custom_claims(CustomClaim { organization: None }, Duration::from_mins(10))
-            .with_issuer(JWT_ISSUER)
-            .with_subject(management_org_id());
-    Ok(keys.key.authenticate(claims).unwrap())
+fn verification_error(err: jwt_simple::Error) -> AuthError {
+    match err.downcast_ref::<JWTError>() {
+        Some(JWTError::TokenHasExpired) => AuthError::Expired,
+        Some(JWTError::TokenNotValidYet) => AuthError::NotYetValid,
+        Some(JWTError::RequiredIssuerMismatch | JWTError::RequiredIssuerMissing) => {
+            AuthError::BadIssuer
+        }
+        _ => AuthError::BadSignature,
+    }
 }
 
-pub fn generate_app_token(
-    keys: &Keys,
-    org_id: OrganizationId,
-    app_id: ApplicationId,
-) -> Result<String> {
-    let claims = Claims::with_custom_claims(
-        CustomClaim {
-            organization: Some(org_id.0),
+/// The claims of an identity provider's token, which are mapped onto permissions as configured
+type IdpClaims = serde_json::Map<String, serde_json::Value>;
+
+/// Verifies a token of the identity provider with its key `kid`, returning the permissions and
+/// expiry of the token
+async fn authenticate_idp_token(
+    jwks: &JwksClient,
+    cfg: &Configuration,
+    token: &str,
+    kid: &str,
+) -> std::result::Result<(Permissions, UnixTimeStamp), AuthError> {
+    // Fetching the key counts towards the verification deadline
+    let deadline = tokio::time::Instant::now()
+        + std::time::Duration::from_millis(cfg.jwt_verification_timeout);
+    let key = tokio::time::timeout_at(deadline, jwks.key(kid))
+        .await
+        .map_err(|_| AuthError::Timeout)?
+        .ok_or(AuthError::BadSignature)?;
+    let claims = verify_with_timeout(
+        deadline.saturating_duration_since(tokio::time::Instant::now()),
+        {
+            let cfg = cfg.clone();
+            let token = token.to_owned();
+            move || key.verify_token::<IdpClaims>(&token, Some(verification_options(&cfg)))
         },
-        Duration::from_hours(24 * 28),
     )
-    .with_issuer(JWT_ISSUER)
-    .with_subject(app_id.0);
-    Ok(keys.key.authenticate(claims).unwrap())
+    .await?
+    .map_err(verification_error)?;
+    let expires_at = claims.expires_at.ok_or(AuthError::MissingClaim("exp"))?;
+
+    Ok((Permissions::from_idp_claims(claims, cfg)?, expires_at))
 }
 
-#[derive(Clone, Debug)]
-pub struct Keys {
-    key: HS256Key,
+/// Runs a token verification on the blocking thread pool, giving up on it after `timeout`
+async fn verify_with_timeout<T: Send + 'static>(
+    timeout: std::time::Duration,
+    verify: impl FnOnce() -> T + Send + 'static,
+) -> std::result::Result<T, AuthError> {
+    match tokio::time::timeout(timeout, tokio::task::spawn_blocking(verify)).await {
+        Ok(Ok(verified)) => Ok(verified),
+        Ok(Err(e)) => Err(AuthError::Internal(to_internal_server_error(e).into())),
+        Err(_) => Err(AuthError::Timeout),
+    }
 }
 
-impl Keys {
-    pub fn new(secret: &[u8]) -> Self {
-        Self {
-            key: HS256Key::from_bytes(secret),
+/// The consecutive authentication failures of a client, and until when (as a UNIX timestamp) it's
+/// blocked
+#[derive(Deserialize, Serialize)]
+struct AuthFailures {
+    count: u32,
+    blocked_until: u64,
+}
+
+kv_def!(AuthFailuresKey, AuthFailures);
+
+impl AuthFailuresKey {
+    fn new(ip: IpAddr) -> Self {
+        AuthFailuresKey(format!("SVIX_AUTH_FAILURES_{}", ip))
+    }
+}
+
+/// Blocks clients with `auth_failure_threshold` consecutive authentication failures before any of
+/// their tokens are verified, so tokens can't be guessed online. The block doubles with every
+/// further failure, and a successful authentication resets it. Clients are told apart by their
+/// address, which is only taken from `X-Forwarded-For` behind a trusted proxy so it can't be
+/// spoofed.
+struct AuthFailureLimiter {
+    cache: Cache,
+    key: AuthFailuresKey,
+    failures: Option<AuthFailures>,
+}
+
+impl AuthFailureLimiter {
+    async fn new<B: Send>(req: &mut RequestParts<B>, cfg: &Configuration) -> Option<Self> {
+        if cfg.auth_failure_threshold == 0 {
+            return None;
+        }
+        let ip = client_ip(
+            req.headers(),
+            req.extensions().get::<ConnectInfo<SocketAddr>>(),
+            cfg.trust_forwarded_for,
+        )?;
+        let Extension(cache) = Extension::<Cache>::from_request(req).await.ok()?;
+
+        let key = AuthFailuresKey::new(ip);
+        // An unavailable cache shouldn't lock every client out, so failures are then let through
+        let failures = cache.get(&key).await.unwrap_or_else(|e| {
+            tracing::warn!("Error fetching authentication failures: {}", e);
+            None
+        });
+        Some(Self {
+            cache,
+            key,
+            failures,
+        })
+    }
+
+    fn check(&self) -> std::result::Result<(), AuthError> {
+        match &self.failures {
+            Some(failures) if failures.blocked_until > Clock::now_since_epoch().as_secs() => {
+                Err(AuthError::RateLimited)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the outcome of the authentication. Failures are counted without any locking, so
+    /// concurrent ones may be undercounted, which only delays the block slightly.
+    async fn record(
+        self,
+        cfg: &Configuration,
+        result: &std::result::Result<Permissions, AuthError>,
+    ) {
+        let res = match result {
+            Ok(_) if self.failures.is_some() => self.cache.delete(&self.key).await,
+            Err(err) if err.counts_towards_limit() => {
+                let count = self.failures.map_or(0, |failures| failures.count) + 1;
+                let backoff = match count.checked_sub(cfg.auth_failure_threshold) {
+                    Some(doublings) => cfg
+                        .auth_failure_backoff
+                        .saturating_mul(2_u64.saturating_pow(doublings))
+                        .min(cfg.auth_failure_max_backoff),
+                    None => 0,
+                };
+                let failures = AuthFailures {
+                    count,
+                    blocked_until: Clock::now_since_epoch().as_secs() + backoff,
+                };
+                // Kept past the block, so the next failure blocks the client for longer
+                let ttl = std::time::Duration::from_secs(backoff + cfg.auth_failure_max_backoff);
+                self.cache.set(&self.key, &failures, ttl).await
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = res {
+            tracing::warn!("Error recording authentication failures: {}", e);
         }
     }
 }
+
+/// Marks that the `jti` of a single-use token has been used
+#[derive(Deserialize, Serialize)]
+struct UsedToken;
+
+kv_def!(UsedTokenKey, UsedToken);
+
+/// Records the first use of a single-use token, and rejects any further ones. The record is kept
+/// for as long as the token could still be accepted. Without a cache to record uses in, single-use
+/// tokens are always rejected.
+async fn mark_token_used<B: Send>(
+    req: &mut RequestParts<B>,
+    cfg: &Configuration,
+    jti: &str,
+    expires_at: UnixTimeStamp,
+) -> std::result::Result<(), AuthError> {
+    let Extension(cache) = Extension::<Cache>::from_request(req)
+        .await
+        .map_err(|e| AuthError::Internal(to_internal_server_error(e).into()))?;
+
+    let time_tolerance = verification_options(cfg)
+        .time_tolerance
+        .map_or(0, |x| x.as_secs());
+    let ttl = expires_at
+        .as_secs()
+        .saturating_sub(Clock::now_since_epoch().as_secs())
+        + time_tolerance;
+
+    let first_use = cache
+        .set_if_not_exists(
+            &UsedTokenKey(format!("SVIX_USED_TOKEN_{}", jti)),
+            &UsedToken,
+            std::time::Duration::from_secs(ttl),
+        )
+        .await
+        .map_err(|e| AuthError::Internal(to_internal_server_error(e).into()))?;
+
+    if !first_use {
+        return Err(AuthError::AlreadyUsed);
+    }
+    Ok(())
+}
+
+impl Permissions {
+    fn from_claims(claims: JWTClaims<CustomClaim>) -> std::result::Result<Self, AuthError> {
+        // Claims of newer versions may restrict the token in ways this server doesn't know of
+        if claims.custom.v > CLAIMS_VERSION {
+            return Err(AuthError::UnsupportedVersion);
+        }
+
+        let verified_claims = Some(Arc::new(claims.clone()));
+        let scopes: HashSet<String> = claims
+            .custom
+            .scope
+            .iter()
+            .flat_map(|scope| scope.split_whitespace())
+            .map(ToOwned::to_owned)
+            .collect();
+        let features: HashSet<String> = claims.custom.features.iter().flatten().cloned().collect();
+        let env = claims.custom.env;
+        let message_id = claims.custom.msg.map(MessageId);
+        let allowed_origins = claims.custom.origins;
+        let label = claims.custom.label;
+        if let Some(label) = &label {
+            if !is_valid_token_label(label) {
+                return Err(AuthError::MalformedClaim("label", ClaimKind::Label));
+            }
+        }
+        if let Some(message_id) = &message_id {
+            message_id
+                .validate()
+                .map_err(|_| AuthError::MalformedClaim("msg", ClaimKind::MessageId))?;
+        }
+
+        let bound_ip_range = claims
+            .custom
+            .cnf
+            .as_ref()
+            .map(|cnf| cnf.ip.parse::<IpRange>())
+            .transpose()
+            .map_err(|_| AuthError::MalformedClaim("cnf", ClaimKind::IpRange))?;
+
+        // If there is an `org` field then it is an Application authentication
+        if let Some(org_id) = claims.custom.organization {
+            let org_id = OrganizationId(org_id);
+            org_id
+                .validate()
+                .map_err(|_| AuthError::MalformedClaim("org", ClaimKind::OrganizationId))?;
+
+            let app_id = ApplicationId(claims.subject.ok_or(AuthError::MissingClaim("sub"))?);
+            app_id
+                .validate()
+                .map_err(|_| AuthError::MalformedClaim("sub", ClaimKind::ApplicationId))?;
+
+            Ok(Permissions {
+                org_id,
+                app_id: Some(app_id),
+                type_: KeyType::Application,
+                additional_org_ids: HashSet::new(),
+                scopes,
+                features,
+                env,
+                message_id,
+                allowed_origins,
+                label,
+                bound_ip_range,
+                claims: verified_claims,
+            })
+        }
+        // Otherwsie it's an Organization authentication
+        else if let Some(org_id) = claims.subject {
+            let org_id = OrganizationId(org_id);
+            org_id
+                .validate()
+                .map_err(|_| AuthError::MalformedClaim("sub", ClaimKind::OrganizationId))?;
+
+            let additional_org_ids = claims
+                .custom
+                .orgs
+                .unwrap_or_default()
+                .into_iter()
+                .map(|org_id| {
+                    let org_id = OrganizationId(org_id);
+                    org_id.validate().map_err(|_| {
+                        AuthError::MalformedClaim("orgs", ClaimKind::OrganizationId)
+                    })?;
+                    Ok(org_id)
+                })
+                .collect::<std::result::Result<_, AuthError>>()?;
+
+            Ok(Permissions {
+                org_id,
+                app_id: None,
+                type_: KeyType::Organization,
+                additional_org_ids,
+                scopes,
+                features,
+                env,
+                message_id,
+                allowed_origins,
+                label,
+                bound_ip_range,
+                claims: verified_claims,
+            })
+        } else {
+            Err(AuthError::MissingClaim("sub"))
+        }
+    }
+}
+
+impl Permissions {
+    /// Maps the claims of an identity provider's token onto permissions, taking the organization
+    /// from the `jwks_org_id_claim`, and the application (for an application token) from the
+    /// `jwks_app_id_claim`
+    fn from_idp_claims(
+        claims: JWTClaims<IdpClaims>,
+        cfg: &Configuration,
+    ) -> std::result::Result<Self, AuthError> {
+        let claim = |name: &str| match name {
+            "sub" => claims.subject.clone(),
+            _ => claims
+                .custom
+                .get(name)
+                .and_then(|value| value.as_str())
+                .map(ToOwned::to_owned),
+        };
+
+        let org_id =
+            OrganizationId(claim(&cfg.jwks_org_id_claim).ok_or(AuthError::MissingClaim("org_id"))?);
+        org_id
+            .validate()
+            .map_err(|_| AuthError::MalformedClaim("org_id", ClaimKind::OrganizationId))?;
+
+        let app_id = cfg
+            .jwks_app_id_claim
+            .as_deref()
+            .and_then(claim)
+            .map(ApplicationId);
+        if let Some(app_id) = &app_id {
+            app_id
+                .validate()
+                .map_err(|_| AuthError::MalformedClaim("app_id", ClaimKind::ApplicationId))?;
+        }
+
+        Ok(Permissions {
+            type_: if app_id.is_some() {
+                KeyType::Application
+            } else {
+                KeyType::Organization
+            },
+            org_id,
+            app_id,
+            additional_org_ids: HashSet::new(),
+            scopes: HashSet::new(),
+            features: HashSet::new(),
+            env: None,
+            message_id: None,
+            allowed_origins: None,
+            label: None,
+            bound_ip_range: None,
+            claims: None,
+        })
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Permissions {
+    /// The permissions of an organization token, so tests can use them without minting a token
+    pub fn for_org(org_id: OrganizationId) -> Self {
+        Permissions {
+            type_: KeyType::Organization,
+            org_id,
+            app_id: None,
+            additional_org_ids: HashSet::new(),
+            scopes: HashSet::new(),
+            features: HashSet::new(),
+            env: None,
+            message_id: None,
+            allowed_origins: None,
+            label: None,
+            bound_ip_range: None,
+            claims: None,
+        }
+    }
+
+    /// The permissions of an application token, so tests can use them without minting a token
+    pub fn for_app(org_id: OrganizationId, app_id: ApplicationId) -> Self {
+        Permissions {
+            type_: KeyType::Application,
+            app_id: Some(app_id),
+            ..Self::for_org(org_id)
+        }
+    }
+}
+
+/// Checks that tokens can be minted with the configured key and verified again, so a misconfigured
+/// key is noticed before any real request is rejected. The token is verified exactly like one sent
+/// with a request.
+pub async fn check_signing_key(cfg: &Configuration) -> Result<()> {
+    check_token_verifies(cfg, &cfg.jwt_secret).await
+}
+
+/// Checks that a token minted with the given keys is accepted by the server
+async fn check_token_verifies(cfg: &Configuration, keys: &Keys) -> Result<()> {
+    // Minted for the server's environment, so only the key itself is checked
+    let mut options = TokenOptions::org(default_org_id());
+    options.claims.env = cfg.environment.clone();
+    let token = mint_token(keys, options)?;
+    let mut req = RequestParts::new(
+        http::Request::builder()
+            .extension(cfg.clone())
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(())
+            .map_err(|e| Error::Generic(e.to_string()))?,
+    );
+
+    match Permissions::authenticate(&mut req).await {
+        Ok(permissions) if permissions.org_id == default_org_id() => Ok(()),
+        Ok(permissions) => Err(Error::Generic(format!(
+            "JWT signing key check failed: the token was verified for {}",
+            permissions
+        ))),
+        Err(err) => Err(Error::Generic(format!(
+            "JWT signing key check failed: a freshly minted token was rejected ({})",
+            err.reason()
+        ))),
+    }
+}
+
+/// Like [`Permissions`], but for endpoints which also allow anonymous access. It's `None` when
+/// there's no `Authorization` header, while a present but invalid token is still rejected.
+pub struct OptionalPermissions(pub Option<Permissions>);
+
+#[async_trait]
+impl<B> FromRequest<B> for OptionalPermissions
+where
+    B: Send,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
+        if !req.headers().contains_key(http::header::AUTHORIZATION) {
+            return Ok(OptionalPermissions(None));
+        }
+
+        Ok(OptionalPermissions(Some(
+            Permissions::from_request(req).await?,
+        )))
+    }
+}
+
+pub struct AuthenticatedOrganization {
+    pub permissions: Permissions,
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for AuthenticatedOrganization
+where
+    B: Send,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
+        let permissions = Permissions::from_request(req).await?;
+        match permissions.type_ {
+            KeyType::Organization => {}
+            KeyType::Application => {
+                return Err(HttpError::permission_denied(None, None).into());
+            }
+        }
+
+        Ok(AuthenticatedOrganization { permissions })
+    }
+}
+
+impl AuthenticatedOrganization {
+    /// Generates an application token for one of the organization's applications, which is
+    /// refused for applications of other organizations.
+    pub async fn generate_app_token(
+        &self,
+        keys: &Keys,
+        db: &DatabaseConnection,
+        app_id: ApplicationId,
+    ) -> Result<String> {
+        let org_id = self.permissions.org_id.clone();
+        let app = application::Entity::secure_find_by_id_or_uid(
+            org_id.clone(),
+            ApplicationIdOrUid(app_id.0),
+        )
+        .one(db)
+        .await?
+        .ok_or_else(|| HttpError::permission_denied(None, None))?;
+        generate_app_token(keys, org_id, app.id)
+    }
+}
+
+#[derive(Deserialize)]
+struct ApplicationPathParams {
+    app_id: ApplicationIdOrUid,
+}
+
+/// Tokens limited to a single message may only be used to read paths of a message, as the handlers
+/// of those check the message with [`Permissions::allows_message`]. Everything else is hidden.
+/// Checked by the [`Permissions`] extractor, so every extractor and handler built on it is covered.
+async fn check_message_path<B: Send>(
+    req: &mut RequestParts<B>,
+    permissions: &Permissions,
+) -> Result<()> {
+    if permissions.message_id.is_none() {
+        return Ok(());
+    }
+
+    let is_read = req.method() == http::Method::GET;
+    let params = Path::<std::collections::HashMap<String, String>>::from_request(req).await;
+    if is_read && matches!(params, Ok(Path(params)) if params.contains_key("msg_id")) {
+        Ok(())
+    } else {
+        Err(HttpError::not_found(None, None).into())
+    }
+}
+
+/// The scopes application tokens may be limited to, as `<resource>:<action>`
+pub const APP_TOKEN_SCOPES: &[&str] = &[
+    "application:read",
+    "application:write",
+    "endpoint:read",
+    "endpoint:write",
+    "message:read",
+    "message:write",
+];
+
+/// The scope an application token needs for the request. The resource is the path segment after
+/// the application (attempts count as messages, anything else as the application itself), and
+/// `GET` and `HEAD` requests only read it.
+fn required_scope<B>(req: &RequestParts<B>) -> String {
+    let path = match req.extensions().get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.path(),
+        None => req.uri().path(),
+    };
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    // Nested routers only see the rest of the path
+    let rest = match segments.iter().position(|&s| s == "app") {
+        Some(i) => segments.get(i + 2..).unwrap_or_default(),
+        None => &segments[..],
+    };
+    let resource = match rest.first() {
+        Some(&"endpoint") => "endpoint",
+        Some(&"msg" | &"attempt") => "message",
+        _ => "application",
+    };
+    let action = if matches!(*req.method(), http::Method::GET | http::Method::HEAD) {
+        "read"
+    } else {
+        "write"
+    };
+    format!("{}:{}", resource, action)
+}
+
+/// Application tokens with a `scope` claim may only be used for the [`required_scope`] of the
+/// request. Organization tokens, and application tokens without the claim, aren't limited.
+fn check_scope<B>(req: &RequestParts<B>, permissions: &Permissions) -> Result<()> {
+    if permissions.type_ != KeyType::Application || permissions.scopes.is_empty() {
+        return Ok(());
+    }
+
+    let scope = required_scope(req);
+    if permissions.scopes.contains(&scope) {
+        Ok(())
+    } else {
+        Err(HttpError::permission_denied(
+            None,
+            Some(format!("This token lacks the `{}` scope.", scope)),
+        )
+        .into())
+    }
+}
+
+/// Fetches the application given in the path. It's looked up by ID or UID in the token's
+/// organization, or otherwise by ID in the other organizations the token may access, in which case
+/// the permissions are scoped to the application's organization for the rest of the request.
+async fn fetch_path_app<B: Send>(
+    req: &mut RequestParts<B>,
+    permissions: &mut Permissions,
+) -> Result<application::Model> {
+    let Path(ApplicationPathParams { app_id }) = Path::<ApplicationPathParams>::from_request(req)
+        .await
+        .map_err(to_internal_server_error)?;
+    let Extension(ref db) = Extension::<DatabaseConnection>::from_request(req)
+        .await
+        .map_err(to_internal_server_error)?;
+    let Extension(ref cfg) = Extension::<Configuration>::from_request(req)
+        .await
+        .map_err(to_internal_server_error)?;
+    // The cache is optional, so the extractors keep working wherever it isn't set up
+    let cache = Extension::<Cache>::from_request(req)
+        .await
+        .ok()
+        .map(|Extension(cache)| cache)
+        .filter(|_| cfg.app_cache_ttl > 0);
+
+    if let Some(app) = find_app_cached(
+        db,
+        cache.as_ref(),
+        std::time::Duration::from_secs(cfg.app_cache_ttl),
+        permissions.org_id.clone(),
+        app_id.clone(),
+    )
+    .await?
+    {
+        return Ok(app);
+    }
+
+    if permissions.additional_org_ids.is_empty() {
+        return Err(HttpError::not_found(None, None).into());
+    }
+
+    // UIDs are only unique within an organization, so the other organizations are searched by ID
+    let app = application::Entity::find()
+        .filter(application::Column::Id.eq(app_id))
+        .filter(application::Column::Deleted.eq(false))
+        .one(db)
+        .await?
+        .filter(|app| permissions.has_org(&app.org_id))
+        .ok_or_else(|| HttpError::not_found(None, None))?;
+
+    permissions.org_id = app.org_id.clone();
+    Ok(app)
+}
+
+pub struct AuthenticatedOrganizationWithApplication {
+    pub permissions: Permissions,
+    pub app: application::Model,
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for AuthenticatedOrganizationWithApplication
+where
+    B: Send,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
+        let mut permissions = Permissions::from_request(req).await?;
+
+        match permissions.type_ {
+            KeyType::Organization => {}
+            KeyType::Application => {
+                return Err(HttpError::permission_denied(None, None).into());
+            }
+        }
+
+        let app = fetch_path_app(req, &mut permissions).await?;
+        Ok(AuthenticatedOrganizationWithApplication { permissions, app })
+    }
+}
+
+type ApplicationModel = application::Model;
+
+kv_def!(PathAppKey, ApplicationModel);
+impl PathAppKey {
+    /// Returns a key for an application resolved by its ID or UID in the given organization
+    fn new(org_id: &OrganizationId, app_id_or_uid: &str) -> PathAppKey {
+        PathAppKey(format!(
+            "{}_PATH_APP_v1_{}_{}",
+            Self::PREFIX_CACHE,
+            org_id,
+            app_id_or_uid
+        ))
+    }
+}
+
+/// Looks up an application by ID or UID in the given organization, going through the cache when
+/// one is given. Only found applications are cached.
+async fn find_app_cached(
+    db: &DatabaseConnection,
+    cache: Option<&Cache>,
+    ttl: std::time::Duration,
+    org_id: OrganizationId,
+    app_id: ApplicationIdOrUid,
+) -> Result<Option<application::Model>> {
+    let cache_key = PathAppKey::new(&org_id, &app_id.0);
+    if let Some(cache) = cache {
+        if let Ok(Some(app)) = cache.get::<ApplicationModel>(&cache_key).await {
+            return Ok(Some(app));
+        }
+    }
+
+    let app = application::Entity::secure_find_by_id_or_uid(org_id, app_id)
+        .one(db)
+        .await?;
+    if let (Some(cache), Some(app)) = (cache, &app) {
+        let _ = cache.set(&cache_key, app, ttl).await;
+    }
+    Ok(app)
+}
+
+/// Removes an application from the cache of applications resolved from paths, both under its ID
+/// and its UID. It has to be called once an application has been updated or deleted, using the
+/// application as it was before, so its previous UID isn't resolved anymore either. Invalidating
+/// before the write would let requests in between cache the application as it was again.
+pub async fn invalidate_cached_app(cache: &Cache, app: &application::Model) {
+    let _ = cache.delete(&PathAppKey::new(&app.org_id, &app.id)).await;
+    if let Some(uid) = &app.uid {
+        let _ = cache.delete(&PathAppKey::new(&app.org_id, uid)).await;
+    }
+}
+
+/// Like [`AuthenticatedOrganizationWithApplication`], but for management tooling: with a token of
+/// the management organization, the application in the path is also looked up (by ID) in every
+/// other organization, in which case the permissions are scoped to the application's organization
+/// for the rest of the request. Other organizations' tokens are treated exactly like in
+/// [`AuthenticatedOrganizationWithApplication`].
+pub struct AuthenticatedOrganizationOrManagementWithApplication {
+    pub permissions: Permissions,
+    pub app: application::Model,
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for AuthenticatedOrganizationOrManagementWithApplication
+where
+    B: Send,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
+        let mut permissions = Permissions::from_request(req).await?;
+
+        match permissions.type_ {
+            KeyType::Organization => {}
+            KeyType::Application => {
+                return Err(HttpError::permission_denied(None, None).into());
+            }
+        }
+
+        let Extension(ref cfg) = Extension::<Configuration>::from_request(req)
+            .await
+            .map_err(to_internal_server_error)?;
+        let app = if permissions.org_id == cfg.management_org_id {
+            fetch_path_app_for_management(req, &mut permissions).await?
+        } else {
+            fetch_path_app(req, &mut permissions).await?
+        };
+        Ok(AuthenticatedOrganizationOrManagementWithApplication { permissions, app })
+    }
+}
+
+/// Fetches the application given in the path for a management token. It's looked up by ID or UID
+/// in the management organization, or otherwise by ID in any organization, in which case the
+/// permissions are scoped to the application's organization for the rest of the request.
+async fn fetch_path_app_for_management<B: Send>(
+    req: &mut RequestParts<B>,
+    permissions: &mut Permissions,
+) -> Result<application::Model> {
+    let Path(ApplicationPathParams { app_id }) = Path::<ApplicationPathParams>::from_request(req)
+        .await
+        .map_err(to_internal_server_error)?;
+    let Extension(ref db) = Extension::<DatabaseConnection>::from_request(req)
+        .await
+        .map_err(to_internal_server_error)?;
+
+    if let Some(app) =
+        application::Entity::secure_find_by_id_or_uid(permissions.org_id.clone(), app_id.clone())
+            .one(db)
+            .await?
+    {
+        return Ok(app);
+    }
+
+    let app = application::Entity::find()
+        .filter(application::Column::Id.eq(app_id))
+        .filter(application::Column::Deleted.eq(false))
+        .one(db)
+        .await?
+        .ok_or_else(|| HttpError::not_found(None, None))?;
+
+    permissions.org_id = app.org_id.clone();
+    Ok(app)
+}
+
+/// Compares application IDs in constant time, so the comparison doesn't leak how much of the ID
+/// matched
+fn app_id_matches(permitted_app_id: &ApplicationId, app_id: &ApplicationId) -> bool {
+    permitted_app_id.as_bytes().ct_eq(app_id.as_bytes()).into()
+}
+
+/// Resolves the application given in the path for either an organization token, or an application
+/// token for that very application. Handlers which treat the two differently can branch on
+/// `scoped_to_single_app`.
+pub struct AuthenticatedOrganizationOrApplication {
+    pub permissions: Permissions,
+    pub app: application::Model,
+    /// Whether the token is an application token, only granting access to `app`
+    pub scoped_to_single_app: bool,
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for AuthenticatedOrganizationOrApplication
+where
+    B: Send,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
+        let mut permissions = Permissions::from_request(req).await?;
+        let app = fetch_path_app(req, &mut permissions).await?;
+
+        let scoped_to_single_app = match &permissions.app_id {
+            Some(permitted_app_id) => {
+                if !app_id_matches(permitted_app_id, &app.id) {
+                    return Err(HttpError::not_found(None, None).into());
+                }
+                true
+            }
+            None => false,
+        };
+        check_scope(req, &permissions)?;
+
+        Ok(AuthenticatedOrganizationOrApplication {
+            permissions,
+            app,
+            scoped_to_single_app,
+        })
+    }
+}
+
+pub struct AuthenticatedApplication {
+    pub permissions: Permissions,
+    pub app: application::Model,
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for AuthenticatedApplication
+where
+    B: Send,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
+        let AuthenticatedOrganizationOrApplication {
+            permissions, app, ..
+        } = AuthenticatedOrganizationOrApplication::from_request(req).await?;
+
+        Ok(AuthenticatedApplication { permissions, app })
+    }
+}
+
+/// Like [`AuthenticatedApplication`] for handlers which only need to know which application the
+/// token grants access to. The application in the path is checked against the one in the
+/// (application) token, without looking it up in the database.
+pub struct AuthenticatedAppScope {
+    pub permissions: Permissions,
+    pub app_id: ApplicationId,
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for AuthenticatedAppScope
+where
+    B: Send,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self> {
+        let permissions = Permissions::from_request(req).await?;
+        let permitted_app_id = permissions
+            .app_id
+            .clone()
+            .ok_or_else(|| HttpError::permission_denied(None, None))?;
+
+        let Path(ApplicationPathParams { app_id }) =
+            Path::<ApplicationPathParams>::from_request(req)
+                .await
+                .map_err(to_internal_server_error)?;
+        if !app_id_matches(&permitted_app_id, &ApplicationId(app_id.0)) {
+            return Err(HttpError::not_found(None, None).into());
+        }
+        check_scope(req, &permissions)?;
+
+        Ok(AuthenticatedAppScope {
+            permissions,
+            app_id: permitted_app_id,
+        })
+    }
+}
+
+const JWT_ISSUER: &str = env!("CARGO_PKG_NAME");
+
+/// The length of a [`generate_jti`] id, enough for 128 bits in base62
+const JTI_LENGTH: usize = 22;
+
+/// Generates a random, URL-safe token id (`jti`) from 128 random bits, encoded in base62
+fn generate_jti() -> String {
+    const ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+    let mut value: u128 = rand::thread_rng().gen();
+    let mut jti = vec![b'0'; JTI_LENGTH];
+    for c in jti.iter_mut().rev() {
+        *c = ALPHABET[(value % 62) as usize];
+        value /= 62;
+    }
+    String::from_utf8(jti).expect("base62 is always valid UTF-8")
+}
+
+/// Only accepts tokens issued by this server, or by one of the configured trusted issuers
+fn verification_options(cfg: &Configuration) -> VerificationOptions {
+    let mut allowed_issuers: HashSet<String> = cfg.jwt_allowed_issuers.iter().cloned().collect();
+    allowed_issuers.insert(JWT_ISSUER.to_owned());
+
+    VerificationOptions {
+        allowed_issuers: Some(allowed_issuers),
+        ..Default::default()
+    }
+}
+
+/// What a token is minted for by [`mint_token`]
+struct TokenOptions {
+    /// The organization, or for application tokens the application, the token is for
+    subject: String,
+    valid_for: Duration,
+    /// When the token becomes valid, if not right away
+    not_before: Option<UnixTimeStamp>,
+    claims: CustomClaim,
+}
+
+impl TokenOptions {
+    fn org(org_id: OrganizationId) -> Self {
+        TokenOptions {
+            subject: org_id.0,
+            valid_for: Duration::from_hours(24 * 365 * 10),
+            not_before: None,
+            claims: CustomClaim {
+                kind: Some(TokenKind::Org),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn management(management_org_id: OrganizationId) -> Self {
+        TokenOptions {
+            subject: management_org_id.0,
+            valid_for: Duration::from_mins(10),
+            not_before: None,
+            claims: CustomClaim {
+                kind: Some(TokenKind::Management),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn app(org_id: OrganizationId, app_id: ApplicationId) -> Self {
+        TokenOptions {
+            subject: app_id.0,
+            valid_for: Duration::from_hours(24 * 28),
+            not_before: None,
+            claims: CustomClaim {
+                organization: Some(org_id.0),
+                kind: Some(TokenKind::Application),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Mints a token issued by this server. Single-use tokens get a random `jti` to track their use by.
+fn mint_token(keys: &Keys, options: TokenOptions) -> Result<String> {
+    let single_use = options.claims.single_use;
+    let mut claims = Claims::with_custom_claims(options.claims, options.valid_for)
+        .with_issuer(JWT_ISSUER)
+        .with_subject(options.subject);
+    if let Some(not_before) = options.not_before {
+        claims = claims.invalid_before(not_before);
+    }
+    if single_use {
+        claims = claims.with_jwt_id(generate_jti());
+    }
+    keys.sign(claims)
+}
+
+pub fn generate_org_token(keys: &Keys, org_id: OrganizationId) -> Result<String> {
+    mint_token(keys, TokenOptions::org(org_id))
+}
+
+/// Like [`generate_org_token`], but the token is only valid from `not_before` on, so it can be
+/// provisioned ahead of time
+pub fn generate_org_token_not_before(
+    keys: &Keys,
+    org_id: OrganizationId,
+    not_before: UnixTimeStamp,
+) -> Result<String> {
+    mint_token(
+        keys,
+        TokenOptions {
+            not_before: Some(not_before),
+            ..TokenOptions::org(org_id)
+        },
+    )
+}
+
+/// Generates an organization token with access to the given beta features
+pub fn generate_org_token_with_features(
+    keys: &Keys,
+    org_id: OrganizationId,
+    features: Vec<String>,
+) -> Result<String> {
+    let mut options = TokenOptions::org(org_id);
+    options.claims.features = Some(features);
+    mint_token(keys, options)
+}
+
+/// Generates an organization token which can also access the applications of the other given
+/// organizations
+pub fn generate_multi_org_token(
+    keys: &Keys,
+    org_id: OrganizationId,
+    additional_org_ids: Vec<OrganizationId>,
+) -> Result<String> {
+    let mut options = TokenOptions::org(org_id);
+    options.claims.orgs = Some(additional_org_ids.into_iter().map(|x| x.0).collect());
+    mint_token(keys, options)
+}
+
+pub fn generate_management_token(keys: &Keys, management_org_id: OrganizationId) -> Result<String> {
+    mint_token(keys, TokenOptions::management(management_org_id))
+}
+
+/// Like [`generate_management_token`], but the token may only be used from the given addresses
+pub fn generate_bound_management_token(
+    keys: &Keys,
+    management_org_id: OrganizationId,
+    ip_range: IpRange,
+) -> Result<String> {
+    let mut options = TokenOptions::management(management_org_id);
+    options.claims.cnf = Some(Confirmation {
+        ip: ip_range.to_string(),
+    });
+    mint_token(keys, options)
+}
+
+/// Like [`generate_management_token`], but the token is rejected after its first use
+pub fn generate_single_use_management_token(
+    keys: &Keys,
+    management_org_id: OrganizationId,
+) -> Result<String> {
+    let mut options = TokenOptions::management(management_org_id);
+    options.claims.single_use = true;
+    mint_token(keys, options)
+}
+
+pub fn generate_app_token(
+    keys: &Keys,
+    org_id: OrganizationId,
+    app_id: ApplicationId,
+) -> Result<String> {
+    mint_token(keys, TokenOptions::app(org_id, app_id))
+}
+
+/// Like [`generate_app_token`], but the token carries a human-readable `label` (e.g. the
+/// integration it's for) which shows up in audit records and introspection. The label is at most
+/// [`MAX_TOKEN_LABEL_LENGTH`] characters long.
+pub fn generate_app_token_labeled(
+    keys: &Keys,
+    org_id: OrganizationId,
+    app_id: ApplicationId,
+    label: String,
+) -> Result<String> {
+    if !is_valid_token_label(&label) {
+        return Err(Error::Validation(format!(
+            "Token labels must be between 1 and {} characters long",
+            MAX_TOKEN_LABEL_LENGTH
+        )));
+    }
+
+    let mut options = TokenOptions::app(org_id, app_id);
+    options.claims.label = Some(label);
+    mint_token(keys, options)
+}
+
+/// Generates an application token which may only be used in the given environment
+pub fn generate_app_token_for_env(
+    keys: &Keys,
+    org_id: OrganizationId,
+    app_id: ApplicationId,
+    env: &str,
+) -> Result<String> {
+    let mut options = TokenOptions::app(org_id, app_id);
+    options.claims.env = Some(env.to_owned());
+    mint_token(keys, options)
+}
+
+/// Generates an application token which browsers may only use from the given origins, e.g. for a
+/// dashboard
+pub fn generate_app_token_for_origins(
+    keys: &Keys,
+    org_id: OrganizationId,
+    app_id: ApplicationId,
+    origins: Vec<String>,
+) -> Result<String> {
+    let mut options = TokenOptions::app(org_id, app_id);
+    options.claims.origins = Some(origins);
+    mint_token(keys, options)
+}
+
+/// Generates an application token which may only be used for the given scopes, out of
+/// [`APP_TOKEN_SCOPES`]
+pub fn generate_scoped_app_token(
+    keys: &Keys,
+    org_id: OrganizationId,
+    app_id: ApplicationId,
+    scopes: &[&str],
+) -> Result<String> {
+    // Without any scopes the token wouldn't be limited at all
+    if scopes.is_empty() {
+        return Err(Error::Validation(
+            "Scoped tokens need at least one scope".to_owned(),
+        ));
+    }
+    if let Some(scope) = scopes
+        .iter()
+        .find(|scope| !APP_TOKEN_SCOPES.contains(scope))
+    {
+        return Err(Error::Validation(format!(
+            "Unknown token scope `{}`",
+            scope
+        )));
+    }
+
+    let mut options = TokenOptions::app(org_id, app_id);
+    options.claims.scope = Some(scopes.join(" "));
+    mint_token(keys, options)
+}
+
+/// Generates an application token which may only be used to read the given message and its
+/// delivery status
+pub fn generate_message_read_token(
+    keys: &Keys,
+    org_id: OrganizationId,
+    app_id: ApplicationId,
+    msg_id: MessageId,
+) -> Result<String> {
+    let mut options = TokenOptions {
+        valid_for: Duration::from_hours(24),
+        ..TokenOptions::app(org_id, app_id)
+    };
+    options.claims.scope = Some("message:read".to_owned());
+    options.claims.msg = Some(msg_id.0);
+    mint_token(keys, options)
+}
+
+/// Generates an application token for an application given by ID or UID. The application is looked
+/// up in the given organization, and the token carries its ID.
+pub async fn generate_app_token_for_uid(
+    keys: &Keys,
+    db: &DatabaseConnection,
+    org_id: OrganizationId,
+    app_id_or_uid: ApplicationIdOrUid,
+) -> Result<String> {
+    let app = application::Entity::secure_find_by_id_or_uid(org_id.clone(), app_id_or_uid)
+        .one(db)
+        .await?
+        .ok_or_else(|| HttpError::not_found(None, None))?;
+    generate_app_token(keys, org_id, app.id)
+}
+
+/// The HMAC algorithm tokens are signed with. Changing it invalidates all previously issued
+/// tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JwtAlgorithm {
+    Hs256,
+    Hs384,
+    Hs512,
+}
+
+/// A symmetric key for one of the supported [`JwtAlgorithm`]s, used both for minting and verifying
+/// tokens
+#[derive(Clone, Debug)]
+enum SymmetricKey {
+    Hs256(HS256Key),
+    Hs384(HS384Key),
+    Hs512(HS512Key),
+}
+
+impl SymmetricKey {
+    fn new(algorithm: JwtAlgorithm, secret: &[u8]) -> Self {
+        match algorithm {
+            JwtAlgorithm::Hs256 => SymmetricKey::Hs256(HS256Key::from_bytes(secret)),
+            JwtAlgorithm::Hs384 => SymmetricKey::Hs384(HS384Key::from_bytes(secret)),
+            JwtAlgorithm::Hs512 => SymmetricKey::Hs512(HS512Key::from_bytes(secret)),
+        }
+    }
+
+    fn algorithm(&self) -> JwtAlgorithm {
+        match self {
+            SymmetricKey::Hs256(_) => JwtAlgorithm::Hs256,
+            SymmetricKey::Hs384(_) => JwtAlgorithm::Hs384,
+            SymmetricKey::Hs512(_) => JwtAlgorithm::Hs512,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            SymmetricKey::Hs256(key) => key.to_bytes(),
+            SymmetricKey::Hs384(key) => key.to_bytes(),
+            SymmetricKey::Hs512(key) => key.to_bytes(),
+        }
+    }
+
+    fn authenticate<C: Serialize + DeserializeOwned>(
+        &self,
+        claims: JWTClaims<C>,
+    ) -> std::result::Result<String, jwt_simple::Error> {
+        match self {
+            SymmetricKey::Hs256(key) => key.authenticate(claims),
+            SymmetricKey::Hs384(key) => key.authenticate(claims),
+            SymmetricKey::Hs512(key) => key.authenticate(claims),
+        }
+    }
+
+    fn verify_token<C: Serialize + DeserializeOwned>(
+        &self,
+        token: &str,
+        options: Option<VerificationOptions>,
+    ) -> std::result::Result<JWTClaims<C>, jwt_simple::Error> {
+        match self {
+            SymmetricKey::Hs256(key) => key.verify_token(token, options),
+            SymmetricKey::Hs384(key) => key.verify_token(token, options),
+            SymmetricKey::Hs512(key) => key.verify_token(token, options),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Keys {
+    key: SymmetricKey,
+}
+
+impl Keys {
+    /// The minimum length (in bytes) of a secret, shorter ones are rejected
+    pub const MIN_SECRET_LENGTH: usize = 16;
+    /// The recommended length (in bytes) of a secret, shorter ones are accepted with a warning
+    pub const RECOMMENDED_SECRET_LENGTH: usize = 32;
+    /// The secret used in dev mode when no `jwt_secret` is configured. It's publicly known, so
+    /// tokens signed with it must never be accepted outside of local development.
+    pub const DEV_SECRET: &'static str = "svix-server-dev-mode-secret-do-not-use-in-production";
+
+    pub fn new(secret: &[u8]) -> Self {
+        Self::with_algorithm(secret, JwtAlgorithm::Hs256)
+    }
+
+    pub fn with_algorithm(secret: &[u8], algorithm: JwtAlgorithm) -> Self {
+        Self {
+            key: SymmetricKey::new(algorithm, secret),
+        }
+    }
+
+    /// Switches to another algorithm, keeping the same secret
+    pub fn into_algorithm(self, algorithm: JwtAlgorithm) -> Self {
+        Self::with_algorithm(&self.key.to_bytes(), algorithm)
+    }
+
+    pub fn algorithm(&self) -> JwtAlgorithm {
+        self.key.algorithm()
+    }
+
+    /// Loads a secret from its base64 representation, rejecting secrets which are too short.
+    pub fn from_base64(secret: &str) -> Result<Self> {
+        let secret = base64::decode(secret)
+            .map_err(|e| Error::Generic(format!("Invalid base64 JWT secret: {}", e)))?;
+        if secret.len() < Self::MIN_SECRET_LENGTH {
+            return Err(Error::Generic(format!(
+                "JWT secret too short: expected at least {} bytes, got {}",
+                Self::MIN_SECRET_LENGTH,
+                secret.len()
+            )));
+        }
+        Ok(Self::new(&secret))
+    }
+
+    /// The length (in bytes) of the secret
+    pub fn secret_len(&self) -> usize {
+        self.key.to_bytes().len()
+    }
+
+    /// Whether this is the publicly known [`Keys::DEV_SECRET`]
+    pub fn is_dev_secret(&self) -> bool {
+        self.key.to_bytes() == Self::DEV_SECRET.as_bytes()
+    }
+
+    /// Generates a new random secret (useful for local development)
+    pub fn generate() -> Self {
+        let secret: [u8; Self::RECOMMENDED_SECRET_LENGTH] = rand::thread_rng().gen();
+        Self::new(&secret)
+    }
+
+    /// Signs the claims into a token
+    fn sign<C: Serialize + DeserializeOwned>(&self, claims: JWTClaims<C>) -> Result<String> {
+        self.key
+            .authenticate(claims)
+            .map_err(|e| Error::Generic(format!("Error signing token: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::Request, response::IntoResponse};
+    use hyper::StatusCode;
+
+    use super::*;
+    use crate::core::types::BaseId;
+
+    fn test_config() -> Configuration {
+        let mut cfg = None;
+        figment::Jail::expect_with(|jail| {
+            jail.set_env(
+                "SVIX_JWT_SECRET",
+                "8KjzRXrKkd9YFcNyqLSIY8JwiaCeRc6WK4UkMnSW",
+            );
+            cfg = Some(crate::cfg::load().unwrap());
+            Ok(())
+        });
+        cfg.unwrap()
+    }
+
+    fn request_parts(cfg: &Configuration, token: Option<&str>) -> RequestParts<()> {
+        let mut req = Request::builder().extension(cfg.clone());
+        if let Some(token) = token {
+            req = req.header(
+                axum::http::header::AUTHORIZATION,
+                format!("Bearer {}", token),
+            );
+        }
+        RequestParts::new(req.body(()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_optional_permissions() {
+        let cfg = test_config();
+
+        // No header
+        let mut req = request_parts(&cfg, None);
+        let OptionalPermissions(permissions) =
+            OptionalPermissions::from_request(&mut req).await.unwrap();
+        assert!(permissions.is_none());
+
+        // Valid token
+        let token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        let OptionalPermissions(permissions) =
+            OptionalPermissions::from_request(&mut req).await.unwrap();
+        assert_eq!(permissions.unwrap().org_id, default_org_id());
+
+        // Invalid token
+        let token = generate_org_token(&Keys::generate(), default_org_id()).unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        let err = OptionalPermissions::from_request(&mut req)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.into_response().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    fn www_authenticate(err: Error) -> String {
+        let res = err.into_response();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        res.headers()[http::header::WWW_AUTHENTICATE]
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn test_www_authenticate_header() {
+        let cfg = test_config();
+
+        // No `Authorization` header
+        let mut req = request_parts(&cfg, None);
+        let err = Permissions::from_request(&mut req).await.err().unwrap();
+        assert_eq!(www_authenticate(err), "Bearer");
+
+        // Malformed token
+        let mut req = request_parts(&cfg, Some("not-a-token"));
+        let err = Permissions::from_request(&mut req).await.err().unwrap();
+        assert_eq!(www_authenticate(err), "Bearer error=\"invalid_token\"");
+
+        // Expired token (well past the verification's clock tolerance)
+        let mut claims =
+            Claims::with_custom_claims(CustomClaim::default(), Duration::from_hours(1))
+                .with_issuer(JWT_ISSUER)
+                .with_subject(default_org_id().0);
+        claims.expires_at = Some(Clock::now_since_epoch() - Duration::from_hours(1));
+        let token = cfg.jwt_secret.key.authenticate(claims).unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        let err = Permissions::from_request(&mut req).await.err().unwrap();
+        assert_eq!(www_authenticate(err), "Bearer error=\"expired_token\"");
+    }
+
+    #[tokio::test]
+    async fn test_not_before() {
+        let cfg = test_config();
+        let time_tolerance = VerificationOptions::default().time_tolerance.unwrap();
+        let authenticate = |not_before| {
+            let cfg = cfg.clone();
+            async move {
+                let token =
+                    generate_org_token_not_before(&cfg.jwt_secret, default_org_id(), not_before)
+                        .unwrap();
+                Permissions::authenticate(&mut request_parts(&cfg, Some(&token))).await
+            }
+        };
+
+        // Used before its activation time
+        let not_before = Clock::now_since_epoch() + time_tolerance + Duration::from_hours(1);
+        let err = authenticate(not_before).await.err().unwrap();
+        assert!(matches!(err, AuthError::NotYetValid));
+        assert_eq!(
+            www_authenticate(err.into()),
+            "Bearer error=\"token_not_yet_valid\""
+        );
+
+        // Within the clock tolerance of its activation time
+        let not_before =
+            Clock::now_since_epoch() + Duration::from_secs(time_tolerance.as_secs() / 2);
+        assert!(authenticate(not_before).await.is_ok());
+
+        // After its activation time
+        let not_before = Clock::now_since_epoch() - Duration::from_mins(1);
+        assert!(authenticate(not_before).await.is_ok());
+    }
+
+    type Fields = std::sync::Arc<std::sync::Mutex<Vec<(&'static str, String)>>>;
+
+    /// Collects the string and integer fields recorded on spans, and those of events
+    #[derive(Clone, Default)]
+    struct RecordedFields {
+        spans: Fields,
+        events: Fields,
+    }
+
+    fn fields_named(fields: &Fields, name: &str) -> Vec<String> {
+        fields
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(field, _)| *field == name)
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    impl RecordedFields {
+        fn get(&self, name: &str) -> Vec<String> {
+            fields_named(&self.spans, name)
+        }
+
+        fn get_event(&self, name: &str) -> Vec<String> {
+            fields_named(&self.events, name)
+        }
+    }
+
+    struct FieldVisitor<'a>(&'a Fields);
+
+    impl tracing::field::Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((field.name(), value.to_owned()));
+        }
+
+        fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((field.name(), value.to_string()));
+        }
+
+        fn record_debug(&mut self, _field: &tracing::field::Field, _value: &dyn std::fmt::Debug) {}
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for RecordedFields {
+        fn on_record(
+            &self,
+            _span: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            values.record(&mut FieldVisitor(&self.spans));
+        }
+
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            event.record(&mut FieldVisitor(&self.events));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_failure_reason_recorded() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let cfg = test_config();
+        let fields = RecordedFields::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(fields.clone()));
+
+        let mut claims =
+            Claims::with_custom_claims(CustomClaim::default(), Duration::from_hours(1))
+                .with_issuer(JWT_ISSUER)
+                .with_subject(default_org_id().0);
+        claims.expires_at = Some(Clock::now_since_epoch() - Duration::from_hours(1));
+        let token = cfg.jwt_secret.key.authenticate(claims).unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        assert!(Permissions::from_request(&mut req).await.is_err());
+
+        assert_eq!(fields.get("reason"), vec!["expired_token".to_owned()]);
+        assert!(fields.get("org_id").is_empty());
+
+        // Counted by reason
+        assert_eq!(
+            fields.get_event("monotonic_counter.auth_failure_total"),
+            vec!["1".to_owned()]
+        );
+        assert_eq!(fields.get_event("reason"), vec!["expired_token".to_owned()]);
+        assert!(fields
+            .get_event("monotonic_counter.auth_success_total")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_auth_success_recorded() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let cfg = test_config();
+        let fields = RecordedFields::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(fields.clone()));
+
+        let token = generate_app_token(
+            &cfg.jwt_secret,
+            default_org_id(),
+            ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned()),
+        )
+        .unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        Permissions::from_request(&mut req).await.unwrap();
+
+        assert_eq!(fields.get("key_type"), vec!["application".to_owned()]);
+        assert_eq!(fields.get("org_id"), vec![default_org_id().0]);
+        assert_eq!(fields.get("outcome"), vec!["success".to_owned()]);
+        assert!(fields.get("reason").is_empty());
+
+        assert_eq!(
+            fields.get_event("monotonic_counter.auth_success_total"),
+            vec!["1".to_owned()]
+        );
+        assert!(fields
+            .get_event("monotonic_counter.auth_failure_total")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_describe() {
+        let cfg = test_config();
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        let describe = |token: String| {
+            let claims = cfg
+                .jwt_secret
+                .key
+                .verify_token::<CustomClaim>(&token, None)
+                .unwrap();
+            let permissions = Permissions::from_claims(claims.clone()).unwrap();
+            let info = serde_json::to_value(permissions.describe(&claims)).unwrap();
+            assert!(info["expiresAt"].is_string());
+            assert!(!info.to_string().contains(&token));
+            info
+        };
+
+        let info = describe(generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap());
+        assert_eq!(info["type"], "organization");
+        assert_eq!(info["orgId"], default_org_id().0);
+        assert!(info.get("appId").is_none());
+
+        let info = describe(
+            generate_app_token(&cfg.jwt_secret, default_org_id(), app_id.clone()).unwrap(),
+        );
+        assert_eq!(info["type"], "application");
+        assert_eq!(info["orgId"], default_org_id().0);
+        assert_eq!(info["appId"], app_id.0);
+        assert!(info.get("label").is_none());
+
+        let info = describe(
+            generate_app_token_labeled(
+                &cfg.jwt_secret,
+                default_org_id(),
+                app_id.clone(),
+                "billing-sync".to_owned(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(info["type"], "application");
+        assert_eq!(info["appId"], app_id.0);
+        assert_eq!(info["label"], "billing-sync");
+        assert!(info.get("scopes").is_none());
+
+        // Everything restricting the token is included
+        let mut options = TokenOptions::app(default_org_id(), app_id);
+        options.claims.scope = Some("message:write message:read".to_owned());
+        options.claims.features = Some(vec!["beta-endpoint".to_owned()]);
+        options.claims.env = Some("sandbox".to_owned());
+        options.claims.msg = Some("msg_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        options.claims.origins = Some(vec!["https://dashboard.example.com".to_owned()]);
+        let info = describe(mint_token(&cfg.jwt_secret, options).unwrap());
+        assert_eq!(
+            info["scopes"],
+            serde_json::json!(["message:read", "message:write"])
+        );
+        assert_eq!(info["features"], serde_json::json!(["beta-endpoint"]));
+        assert_eq!(info["env"], "sandbox");
+        assert_eq!(info["messageId"], "msg_2EhUTiNjrsCVDKRRauIWSP1dSpX");
+        assert_eq!(
+            info["allowedOrigins"],
+            serde_json::json!(["https://dashboard.example.com"])
+        );
+    }
+
+    #[derive(Default)]
+    struct RecordingAuditSink(std::sync::Mutex<Vec<AuthAuditEvent>>);
+
+    #[async_trait]
+    impl crate::core::auth_audit::AuthAuditSink for RecordingAuditSink {
+        async fn record(&self, event: AuthAuditEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_audit_sink() {
+        let cfg = test_config();
+        let sink = std::sync::Arc::new(RecordingAuditSink::default());
+
+        let token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        req.extensions_mut()
+            .insert(sink.clone() as SharedAuthAuditSink);
+        Permissions::from_request(&mut req).await.unwrap();
+
+        let mut req = request_parts(&cfg, Some("not-a-token"));
+        req.extensions_mut()
+            .insert(sink.clone() as SharedAuthAuditSink);
+        assert!(Permissions::from_request(&mut req).await.is_err());
+
+        // Events are recorded in the background
+        for _ in 0..100 {
+            if sink.0.lock().unwrap().len() == 2 {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        let events = sink.0.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].org_id, Some(default_org_id()));
+        assert_eq!(events[0].key_type, Some(KeyType::Organization));
+        assert_eq!(events[0].outcome, AuthAuditOutcome::Success);
+        assert_eq!(events[1].org_id, None);
+        assert_eq!(
+            events[1].outcome,
+            AuthAuditOutcome::Failure {
+                reason: "invalid_token"
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_permissions_claims() {
+        let cfg = test_config();
+        let token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let token_cache = TokenCache::new(10, std::time::Duration::from_secs(60));
+
+        // Both when verified, and when taken from the cache
+        for _ in 0..2 {
+            let mut req = request_parts(&cfg, Some(&token));
+            req.extensions_mut().insert(token_cache.clone());
+            let permissions = Permissions::from_request(&mut req).await.unwrap();
+
+            let claims = permissions.claims.unwrap();
+            let now = Clock::now_since_epoch();
+            assert!(claims.issued_at.unwrap() <= now);
+            assert!(claims.expires_at.unwrap() > now + Duration::from_days(365));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_cache_hit_skips_verification() {
+        let cfg = test_config();
+        let token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let token_cache = TokenCache::new(10, std::time::Duration::from_secs(60));
+
+        let mut req = request_parts(&cfg, Some(&token));
+        req.extensions_mut().insert(token_cache.clone());
+        Permissions::from_request(&mut req).await.unwrap();
+
+        // With a different key the token no longer verifies, so it can only be accepted from the
+        // cache, no matter how often it's used
+        let other_cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            jwt_secret: Keys::generate(),
+            ..(*cfg).clone()
+        });
+        for _ in 0..1000 {
+            let mut req = request_parts(&other_cfg, Some(&token));
+            req.extensions_mut().insert(token_cache.clone());
+            let permissions = Permissions::from_request(&mut req).await.unwrap();
+            assert_eq!(permissions.org_id, default_org_id());
+        }
+
+        // While without the cache it's verified, and rejected
+        let mut req = request_parts(&other_cfg, Some(&token));
+        assert!(Permissions::from_request(&mut req).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cross_type_ids_rejected() {
+        let cfg = test_config();
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+
+        // An application id as the `sub` of an organization token
+        let org_claims =
+            Claims::with_custom_claims(CustomClaim::default(), Duration::from_hours(1))
+                .with_issuer(JWT_ISSUER)
+                .with_subject(app_id.0.clone());
+
+        // And the ids swapped in an application token
+        let app_claims = Claims::with_custom_claims(
+            CustomClaim {
+                organization: Some(app_id.0.clone()),
+                ..Default::default()
+            },
+            Duration::from_hours(1),
+        )
+        .with_issuer(JWT_ISSUER)
+        .with_subject(default_org_id().0);
+
+        // Or an organization id as the application
+        let app_sub_claims = Claims::with_custom_claims(
+            CustomClaim {
+                organization: Some(default_org_id().0),
+                ..Default::default()
+            },
+            Duration::from_hours(1),
+        )
+        .with_issuer(JWT_ISSUER)
+        .with_subject(default_org_id().0);
+
+        // The responses are exactly those from before `AuthError` was introduced
+        for (claims, body) in [
+            (
+                org_claims,
+                r#"{"code":"bad_token","detail":"`sub' is not a valid organization id."}"#,
+            ),
+            (
+                app_claims,
+                r#"{"code":"bad token","detail":"`org` is not a valid organization id"}"#,
+            ),
+            (
+                app_sub_claims,
+                r#"{"code":"bad token","detail":"`sub` is not a valid application id"}"#,
+            ),
+        ] {
+            let token = cfg.jwt_secret.key.authenticate(claims).unwrap();
+            let mut req = request_parts(&cfg, Some(&token));
+            let err = Permissions::from_request(&mut req).await.err().unwrap();
+            let res = err.into_response();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let res_body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(std::str::from_utf8(&res_body).unwrap(), body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_without_exp_rejected() {
+        let cfg = test_config();
+
+        let mut claims =
+            Claims::with_custom_claims(CustomClaim::default(), Duration::from_hours(1))
+                .with_issuer(JWT_ISSUER)
+                .with_subject(default_org_id().0);
+        claims.expires_at = None;
+        let token = cfg.jwt_secret.key.authenticate(claims).unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        let err = Permissions::from_request(&mut req).await.err().unwrap();
+        assert_eq!(err.into_response().status(), StatusCode::UNAUTHORIZED);
+
+        let token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        let permissions = Permissions::from_request(&mut req).await.unwrap();
+        assert_eq!(permissions.org_id, default_org_id());
+    }
+
+    #[tokio::test]
+    async fn test_management_token() {
+        let mut cfg = test_config();
+        assert_eq!(cfg.management_org_id.0, "org_00000000000SvixManagement00");
+
+        for _ in 0..2 {
+            let token =
+                generate_management_token(&cfg.jwt_secret, cfg.management_org_id.clone()).unwrap();
+            let mut req = request_parts(&cfg, Some(&token));
+            let permissions = Permissions::from_request(&mut req).await.unwrap();
+            assert_eq!(permissions.org_id, cfg.management_org_id);
+
+            // And again with a custom management organization
+            cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+                management_org_id: OrganizationId("org_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned()),
+                ..(*cfg).clone()
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_single_use_management_token() {
+        let cfg = test_config();
+        let cache = crate::core::cache::memory::new();
+        let token =
+            generate_single_use_management_token(&cfg.jwt_secret, cfg.management_org_id.clone())
+                .unwrap();
+
+        let mut req = request_parts(&cfg, Some(&token));
+        req.extensions_mut().insert(cache.clone());
+        let permissions = Permissions::from_request(&mut req).await.unwrap();
+        assert_eq!(permissions.org_id, cfg.management_org_id);
+
+        let mut req = request_parts(&cfg, Some(&token));
+        req.extensions_mut().insert(cache.clone());
+        let err = Permissions::from_request(&mut req).await.err().unwrap();
+        assert!(err.to_string().contains("token_already_used"));
+        assert_eq!(err.into_response().status(), StatusCode::UNAUTHORIZED);
+
+        // Other single-use tokens are unaffected
+        let token =
+            generate_single_use_management_token(&cfg.jwt_secret, cfg.management_org_id.clone())
+                .unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        req.extensions_mut().insert(cache.clone());
+        Permissions::from_request(&mut req).await.unwrap();
+
+        // A request rejected for another reason doesn't use the token up
+        let mut options = TokenOptions::management(cfg.management_org_id.clone());
+        options.claims.single_use = true;
+        options.claims.origins = Some(vec!["https://dashboard.example.com".to_owned()]);
+        let token = mint_token(&cfg.jwt_secret, options).unwrap();
+        let authenticate = |origin: &str| {
+            let mut req = request_parts(&cfg, Some(&token));
+            req.extensions_mut().insert(cache.clone());
+            req.headers_mut()
+                .insert(http::header::ORIGIN, origin.parse().unwrap());
+            async move { Permissions::authenticate(&mut req).await }
+        };
+        assert!(matches!(
+            authenticate("https://evil.example.com").await,
+            Err(AuthError::OriginMismatch)
+        ));
+        authenticate("https://dashboard.example.com").await.unwrap();
+        assert!(matches!(
+            authenticate("https://dashboard.example.com").await,
+            Err(AuthError::AlreadyUsed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_allowed_issuers() {
+        let cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            jwt_allowed_issuers: vec!["svix-server-us".to_owned()],
+            ..(*test_config()).clone()
+        });
+        let token = |issuer: Option<&str>| {
+            let claims =
+                Claims::with_custom_claims(CustomClaim::default(), Duration::from_hours(1))
+                    .with_subject(default_org_id().0);
+            let claims = match issuer {
+                Some(issuer) => claims.with_issuer(issuer),
+                None => claims,
+            };
+            cfg.jwt_secret.key.authenticate(claims).unwrap()
+        };
+
+        for issuer in [JWT_ISSUER, "svix-server-us"] {
+            let mut req = request_parts(&cfg, Some(&token(Some(issuer))));
+            let permissions = Permissions::from_request(&mut req).await.unwrap();
+            assert_eq!(permissions.org_id, default_org_id());
+        }
+
+        for issuer in [Some("svix-server-eu"), None] {
+            let mut req = request_parts(&cfg, Some(&token(issuer)));
+            let err = Permissions::from_request(&mut req).await.err().unwrap();
+            assert_eq!(err.into_response().status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_allowed_origins() {
+        let cfg = test_config();
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        let dashboard_token = generate_app_token_for_origins(
+            &cfg.jwt_secret,
+            default_org_id(),
+            app_id.clone(),
+            vec!["https://dashboard.example.com".to_owned()],
+        )
+        .unwrap();
+        let server_token = generate_app_token(&cfg.jwt_secret, default_org_id(), app_id).unwrap();
+        let authenticate = |token: &str, origin: Option<&str>| {
+            let mut req = request_parts(&cfg, Some(token));
+            if let Some(origin) = origin {
+                req.headers_mut()
+                    .insert(http::header::ORIGIN, origin.parse().unwrap());
+            }
+            async move { Permissions::authenticate(&mut req).await }
+        };
+
+        // Matching origin
+        let permissions = authenticate(&dashboard_token, Some("https://dashboard.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(
+            permissions.allowed_origins,
+            Some(vec!["https://dashboard.example.com".to_owned()])
+        );
+
+        // Mismatched origin, or none at all
+        for origin in [Some("https://evil.example.com"), None] {
+            let err = authenticate(&dashboard_token, origin).await.err().unwrap();
+            assert!(matches!(err, AuthError::OriginMismatch));
+            assert_eq!(
+                Error::from(err).into_response().status(),
+                StatusCode::FORBIDDEN
+            );
+        }
+
+        // Tokens without the claim are unaffected
+        for origin in [Some("https://evil.example.com"), None] {
+            authenticate(&server_token, origin).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_failure_limiter() {
+        let cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            auth_failure_threshold: 3,
+            auth_failure_backoff: 60,
+            ..(*test_config()).clone()
+        });
+        let cache = crate::core::cache::memory::new();
+        let valid = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let invalid = generate_org_token(&Keys::generate(), default_org_id()).unwrap();
+        let authenticate = |token: &str, peer: &str, forwarded_for: &str| {
+            let mut req = request_parts(&cfg, Some(token));
+            req.extensions_mut().insert(cache.clone());
+            req.extensions_mut()
+                .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+            req.headers_mut()
+                .insert("x-forwarded-for", forwarded_for.parse().unwrap());
+            async move { Permissions::authenticate(&mut req).await }
+        };
+
+        // Repeated failures trip the limiter, even for valid tokens, and without being spoofable
+        // through `X-Forwarded-For` when there's no trusted proxy
+        for forwarded_for in ["10.2.0.1", "10.2.0.2", "10.2.0.3"] {
+            assert!(matches!(
+                authenticate(&invalid, "10.1.0.1:1234", forwarded_for).await,
+                Err(AuthError::BadSignature)
+            ));
+        }
+        for token in [&invalid, &valid] {
+            let err = authenticate(token, "10.1.0.1:1234", "10.2.0.4")
+                .await
+                .err()
+                .unwrap();
+            assert!(matches!(err, AuthError::RateLimited));
+            assert_eq!(
+                Error::from(err).into_response().status(),
+                StatusCode::TOO_MANY_REQUESTS
+            );
+        }
+
+        // Other clients are unaffected
+        authenticate(&valid, "10.1.0.2:1234", "10.2.0.1")
+            .await
+            .unwrap();
+
+        // A success resets the count
+        for _ in 0..2 {
+            for _ in 0..2 {
+                assert!(matches!(
+                    authenticate(&invalid, "10.1.0.3:1234", "10.2.0.1").await,
+                    Err(AuthError::BadSignature)
+                ));
+            }
+            authenticate(&valid, "10.1.0.3:1234", "10.2.0.1")
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bound_token() {
+        let cfg = test_config();
+        let token = generate_bound_management_token(
+            &cfg.jwt_secret,
+            cfg.management_org_id.clone(),
+            "10.1.0.0/16".parse().unwrap(),
+        )
+        .unwrap();
+        let request = |cfg: &Configuration, token: &str, peer: &str, forwarded_for: &str| {
+            let mut req = request_parts(cfg, Some(token));
+            req.extensions_mut()
+                .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+            req.headers_mut()
+                .insert("x-forwarded-for", forwarded_for.parse().unwrap());
+            req
+        };
+
+        // Matching address
+        let mut req = request(&cfg, &token, "10.1.2.3:1234", "10.2.0.1");
+        Permissions::from_request(&mut req).await.unwrap();
+
+        // Mismatching address, ignoring the untrusted header
+        let mut req = request(&cfg, &token, "10.2.0.1:1234", "10.1.2.3");
+        let err = Permissions::from_request(&mut req).await.err().unwrap();
+        assert_eq!(err.into_response().status(), StatusCode::FORBIDDEN);
+
+        // Behind a trusted proxy the header is used instead
+        let proxied_cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            trust_forwarded_for: true,
+            ..(*cfg).clone()
+        });
+        let mut req = request(&proxied_cfg, &token, "10.2.0.1:1234", "10.1.2.3");
+        Permissions::from_request(&mut req).await.unwrap();
+
+        // Tokens without the claim can be used from anywhere
+        let token =
+            generate_management_token(&cfg.jwt_secret, cfg.management_org_id.clone()).unwrap();
+        let mut req = request(&cfg, &token, "10.2.0.1:1234", "10.2.0.1");
+        Permissions::from_request(&mut req).await.unwrap();
+    }
+
+    #[test]
+    fn test_generate_jti() {
+        let jti = generate_jti();
+        assert_eq!(jti.len(), JTI_LENGTH);
+        assert!(jti.chars().all(|c| c.is_ascii_alphanumeric()));
+
+        let jtis: HashSet<String> = (0..100_000).map(|_| generate_jti()).collect();
+        assert_eq!(jtis.len(), 100_000);
+    }
+
+    #[tokio::test]
+    async fn test_auth_error() {
+        let cfg = test_config();
+        let claims = |issuer: &str, subject: &str| {
+            Claims::with_custom_claims(CustomClaim::default(), Duration::from_hours(1))
+                .with_issuer(issuer)
+                .with_subject(subject)
+        };
+        let authenticate = |token: Option<String>| {
+            let mut req = request_parts(&cfg, token.as_deref());
+            async move { Permissions::authenticate(&mut req).await.err().unwrap() }
+        };
+
+        assert!(matches!(authenticate(None).await, AuthError::MissingHeader));
+
+        let token = generate_org_token(&Keys::generate(), default_org_id()).unwrap();
+        assert!(matches!(
+            authenticate(Some(token)).await,
+            AuthError::BadSignature
+        ));
+        assert!(matches!(
+            authenticate(Some("not-a-token".to_owned())).await,
+            AuthError::MalformedToken
+        ));
+
+        let mut expired = claims(JWT_ISSUER, &default_org_id().0);
+        expired.expires_at = Some(Clock::now_since_epoch() - Duration::from_hours(1));
+        let token = cfg.jwt_secret.key.authenticate(expired).unwrap();
+        assert!(matches!(
+            authenticate(Some(token)).await,
+            AuthError::Expired
+        ));
+
+        let token = cfg
+            .jwt_secret
+            .key
+            .authenticate(claims("svix-server-eu", &default_org_id().0))
+            .unwrap();
+        assert!(matches!(
+            authenticate(Some(token)).await,
+            AuthError::BadIssuer
+        ));
+
+        let mut without_exp = claims(JWT_ISSUER, &default_org_id().0);
+        without_exp.expires_at = None;
+        let token = cfg.jwt_secret.key.authenticate(without_exp).unwrap();
+        assert!(matches!(
+            authenticate(Some(token)).await,
+            AuthError::MissingClaim("exp")
+        ));
+
+        let token = cfg
+            .jwt_secret
+            .key
+            .authenticate(claims(JWT_ISSUER, "app_2EhUTiNjrsCVDKRRauIWSP1dSpX"))
+            .unwrap();
+        let err = authenticate(Some(token)).await;
+        assert!(matches!(
+            err,
+            AuthError::MalformedClaim("sub", ClaimKind::OrganizationId)
+        ));
+        assert_eq!(
+            Error::from(err).into_response().status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let token = generate_bound_management_token(
+            &cfg.jwt_secret,
+            cfg.management_org_id.clone(),
+            "10.1.0.0/16".parse().unwrap(),
+        )
+        .unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        req.extensions_mut()
+            .insert(ConnectInfo("10.2.0.1:1234".parse::<SocketAddr>().unwrap()));
+        let err = Permissions::authenticate(&mut req).await.err().unwrap();
+        assert!(matches!(err, AuthError::Forbidden));
+        assert_eq!(
+            Error::from(err).into_response().status(),
+            StatusCode::FORBIDDEN
+        );
+
+        // Single-use tokens need a cache
+        let token =
+            generate_single_use_management_token(&cfg.jwt_secret, cfg.management_org_id.clone())
+                .unwrap();
+        assert!(matches!(
+            authenticate(Some(token.clone())).await,
+            AuthError::Internal(_)
+        ));
+        let cache = crate::core::cache::memory::new();
+        let request = || {
+            let mut req = request_parts(&cfg, Some(&token));
+            req.extensions_mut().insert(cache.clone());
+            req
+        };
+        Permissions::authenticate(&mut request()).await.unwrap();
+        assert!(matches!(
+            Permissions::authenticate(&mut request())
+                .await
+                .err()
+                .unwrap(),
+            AuthError::AlreadyUsed
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_authorization_header() {
+        let cfg = test_config();
+        let token = "a".repeat(cfg.jwt_max_header_length);
+        let mut req = request_parts(&cfg, Some(&token));
+        let err = Permissions::authenticate(&mut req).await.err().unwrap();
+        assert!(matches!(err, AuthError::HeaderTooLarge));
+        assert_eq!(
+            Error::from(err).into_response().status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // Just short enough to be verified (and rejected as invalid)
+        let token = format!(
+            "a.b.{}",
+            "c".repeat(cfg.jwt_max_header_length - "Bearer a.b.".len())
+        );
+        let mut req = request_parts(&cfg, Some(&token));
+        let err = Permissions::authenticate(&mut req).await.err().unwrap();
+        assert!(matches!(err, AuthError::BadSignature));
+    }
+
+    #[tokio::test]
+    async fn test_token_kind() {
+        let cfg = test_config();
+        let app_id = "app_2EhUTiNjrsCVDKRRauIWSP1dSpX";
+        let token = |kind, organization: Option<&str>, subject: &str| {
+            let claims = Claims::with_custom_claims(
+                CustomClaim {
+                    organization: organization.map(ToOwned::to_owned),
+                    kind,
+                    ..Default::default()
+                },
+                Duration::from_hours(1),
+            )
+            .with_issuer(JWT_ISSUER)
+            .with_subject(subject);
+            cfg.jwt_secret.key.authenticate(claims).unwrap()
+        };
+        let authenticate = |cfg: Configuration, token: String| async move {
+            Permissions::authenticate(&mut request_parts(&cfg, Some(&token))).await
+        };
+        let org_id = default_org_id().0;
+        let management_org_id = cfg.management_org_id.0.clone();
+
+        // Claims of one type of token, minted as another
+        for token in [
+            token(Some(TokenKind::Application), None, &org_id),
+            token(Some(TokenKind::Management), None, &org_id),
+            token(Some(TokenKind::Org), Some(&org_id), app_id),
+            token(Some(TokenKind::Management), Some(&org_id), app_id),
+        ] {
+            assert!(matches!(
+                authenticate(cfg.clone(), token).await,
+                Err(AuthError::KindMismatch)
+            ));
+        }
+
+        for token in [
+            token(Some(TokenKind::Org), None, &org_id),
+            token(Some(TokenKind::Org), None, &management_org_id),
+            token(Some(TokenKind::Management), None, &management_org_id),
+            token(Some(TokenKind::Application), Some(&org_id), app_id),
+            generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap(),
+            generate_management_token(&cfg.jwt_secret, cfg.management_org_id.clone()).unwrap(),
+            generate_app_token(
+                &cfg.jwt_secret,
+                default_org_id(),
+                ApplicationId(app_id.to_owned()),
+            )
+            .unwrap(),
+        ] {
+            authenticate(cfg.clone(), token).await.unwrap();
+        }
+
+        // Tokens minted before the claim are only accepted unless it's required
+        let legacy = token(None, None, &org_id);
+        authenticate(cfg.clone(), legacy.clone()).await.unwrap();
+        let strict_cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            require_token_kind: true,
+            ..(*cfg).clone()
+        });
+        assert!(matches!(
+            authenticate(strict_cfg, legacy).await,
+            Err(AuthError::MissingClaim("kind"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_token_label() {
+        let cfg = test_config();
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        let sink = std::sync::Arc::new(RecordingAuditSink::default());
+        let from_request = |token: String| {
+            let mut req = request_parts(&cfg, Some(&token));
+            req.extensions_mut()
+                .insert(sink.clone() as SharedAuthAuditSink);
+            async move { Permissions::from_request(&mut req).await }
+        };
+
+        let labeled = generate_app_token_labeled(
+            &cfg.jwt_secret,
+            default_org_id(),
+            app_id.clone(),
+            "billing-sync".to_owned(),
+        )
+        .unwrap();
+        let permissions = from_request(labeled).await.unwrap();
+        assert_eq!(permissions.label.as_deref(), Some("billing-sync"));
+        assert_eq!(
+            sink.0.lock().unwrap()[0].label.as_deref(),
+            Some("billing-sync")
+        );
+
+        // The label doesn't grant or deny anything
+        let unlabeled =
+            generate_app_token(&cfg.jwt_secret, default_org_id(), app_id.clone()).unwrap();
+        let unlabeled = from_request(unlabeled).await.unwrap();
+        assert_eq!(unlabeled.label, None);
+        assert_eq!(sink.0.lock().unwrap()[1].label, None);
+        assert_eq!(permissions.type_, unlabeled.type_);
+        assert_eq!(permissions.org_id, unlabeled.org_id);
+        assert_eq!(permissions.app_id, unlabeled.app_id);
+        assert_eq!(permissions.scopes, unlabeled.scopes);
+        assert_eq!(permissions.features, unlabeled.features);
+
+        // Labels are length-limited, both when minting tokens and when verifying them
+        assert!(generate_app_token_labeled(
+            &cfg.jwt_secret,
+            default_org_id(),
+            app_id.clone(),
+            "a".repeat(MAX_TOKEN_LABEL_LENGTH),
+        )
+        .is_ok());
+        for label in ["".to_owned(), "a".repeat(MAX_TOKEN_LABEL_LENGTH + 1)] {
+            assert!(generate_app_token_labeled(
+                &cfg.jwt_secret,
+                default_org_id(),
+                app_id.clone(),
+                label.clone(),
+            )
+            .is_err());
+
+            let claims = Claims::with_custom_claims(
+                CustomClaim {
+                    organization: Some(default_org_id().0),
+                    label: Some(label),
+                    kind: Some(TokenKind::Application),
+                    ..Default::default()
+                },
+                Duration::from_hours(1),
+            )
+            .with_issuer(JWT_ISSUER)
+            .with_subject(app_id.0.clone());
+            let token = cfg.jwt_secret.key.authenticate(claims).unwrap();
+            let err = Permissions::authenticate(&mut request_parts(&cfg, Some(&token)))
+                .await
+                .err()
+                .unwrap();
+            assert!(matches!(
+                err,
+                AuthError::MalformedClaim("label", ClaimKind::Label)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_claims_version() {
+        let cfg = test_config();
+        let authenticate = |token: String| {
+            let cfg = cfg.clone();
+            async move { Permissions::authenticate(&mut request_parts(&cfg, Some(&token))).await }
+        };
+        let token = |v| {
+            let claims = Claims::with_custom_claims(
+                CustomClaim {
+                    v,
+                    ..Default::default()
+                },
+                Duration::from_hours(1),
+            )
+            .with_issuer(JWT_ISSUER)
+            .with_subject(default_org_id().0);
+            cfg.jwt_secret.key.authenticate(claims).unwrap()
+        };
+
+        authenticate(token(CLAIMS_VERSION)).await.unwrap();
+        authenticate(generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap())
+            .await
+            .unwrap();
+
+        let err = authenticate(token(CLAIMS_VERSION + 1)).await.err().unwrap();
+        assert!(matches!(err, AuthError::UnsupportedVersion));
+        assert_eq!(
+            Error::from(err).into_response().status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        // Tokens without a version are version 1
+        let legacy = Claims::with_custom_claims(IdpClaims::new(), Duration::from_hours(1))
+            .with_issuer(JWT_ISSUER)
+            .with_subject(default_org_id().0);
+        let legacy = cfg.jwt_secret.key.authenticate(legacy).unwrap();
+        authenticate(legacy).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_multi_org_token() {
+        let cfg = test_config();
+        let permitted_org = OrganizationId::generate();
+        let other_org = OrganizationId::generate();
+
+        let token = generate_multi_org_token(
+            &cfg.jwt_secret,
+            default_org_id(),
+            vec![permitted_org.clone()],
+        )
+        .unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        let permissions = Permissions::authenticate(&mut req).await.unwrap();
+        assert_eq!(permissions.org_id, default_org_id());
+        assert!(permissions.has_org(&default_org_id()));
+        assert!(permissions.has_org(&permitted_org));
+        assert!(!permissions.has_org(&other_org));
+
+        // Plain organization tokens only grant access to their own organization
+        let token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let mut req = request_parts(&cfg, Some(&token));
+        let permissions = Permissions::authenticate(&mut req).await.unwrap();
+        assert!(permissions.has_org(&default_org_id()));
+        assert!(!permissions.has_org(&permitted_org));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_auth_scheme() {
+        let cfg = test_config();
+        let token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let authenticate = |header: String| {
+            let mut req = RequestParts::new(
+                Request::builder()
+                    .extension(cfg.clone())
+                    .header(axum::http::header::AUTHORIZATION, header)
+                    .body(())
+                    .unwrap(),
+            );
+            async move { Permissions::authenticate(&mut req).await }
+        };
+
+        for header in [
+            "Basic dXNlcjpwYXNzd29yZA==".to_owned(),
+            format!("Digest {}", token),
+            token.clone(),
+        ] {
+            let err = authenticate(header.clone()).await.err().unwrap();
+            assert!(matches!(err, AuthError::UnsupportedScheme), "{}", header);
+
+            let res = Error::from(err).into_response();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(res.headers()[http::header::WWW_AUTHENTICATE], "Bearer");
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["code"], "unsupported_auth_scheme");
+        }
+
+        // Bearer tokens are verified as usual, and their failures kept apart
+        authenticate(format!("Bearer {}", token)).await.unwrap();
+        assert!(matches!(
+            authenticate("Bearer not-a-token".to_owned()).await,
+            Err(AuthError::MalformedToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_token() {
+        let cfg = test_config();
+
+        for token in [
+            "not-a-token-at-all-even-if-long-enough",
+            "short.to.be",
+            "two-segments-only.aaaaaaaaaaaaaaaaaaaaa",
+            "four.segments.aaaaaaaaaaaaaaaaaa.aaaaaa",
+            ".empty-first-segment.aaaaaaaaaaaaaaaaaa",
+            "empty-last-segment.aaaaaaaaaaaaaaaaaaa.",
+            "empty..middle-segment-aaaaaaaaaaaaaaaaa",
+            "not+base64url.aaaaaaaaaaaaaaaaa.aaaaaaa",
+            "padded.aaaaaaaaaaaaaaaaaaaaaaa.aaaaaaa==",
+        ] {
+            assert!(!is_well_formed_token(token), "{}", token);
+            let mut req = request_parts(&cfg, Some(token));
+            let err = Permissions::authenticate(&mut req).await.err().unwrap();
+            assert!(matches!(err, AuthError::MalformedToken), "{}", token);
+        }
+        assert!(!is_well_formed_token(&format!(
+            "a.b.{}",
+            "c".repeat(MAX_TOKEN_LENGTH)
+        )));
+
+        // Well-formed tokens are verified, whether or not they're valid
+        let token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        assert!(is_well_formed_token(&token));
+        let mut req = request_parts(&cfg, Some(&token));
+        Permissions::authenticate(&mut req).await.unwrap();
+
+        let token = "aaaaaaaaaa.bbbbbbbbbb.cccccccccc";
+        assert!(is_well_formed_token(token));
+        let mut req = request_parts(&cfg, Some(token));
+        let err = Permissions::authenticate(&mut req).await.err().unwrap();
+        assert!(matches!(err, AuthError::BadSignature));
+    }
+
+    #[tokio::test]
+    async fn test_verification_timeout() {
+        let timeout = std::time::Duration::from_millis(10);
+
+        let verified = verify_with_timeout(timeout, || 1).await.unwrap();
+        assert_eq!(verified, 1);
+
+        let err = verify_with_timeout(timeout, || {
+            std::thread::sleep(std::time::Duration::from_millis(200))
+        })
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(err, AuthError::Timeout));
+        assert_eq!(
+            Error::from(err).into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    /// Serves the given key set, counting how often it's fetched
+    async fn start_jwks_server(
+        jwks: std::sync::Arc<std::sync::Mutex<serde_json::Value>>,
+        fetches: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    ) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/jwks", listener.local_addr().unwrap());
+        let router = axum::Router::new().route(
+            "/jwks",
+            axum::routing::get(move || {
+                fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let jwks = jwks.lock().unwrap().clone();
+                async move {
+                    (
+                        [(http::header::CACHE_CONTROL, "max-age=3600")],
+                        axum::Json(jwks),
+                    )
+                }
+            }),
+        );
+        tokio::spawn(async move {
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service())
+                .await
+                .unwrap();
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_jwks() {
+        const IDP_ISSUER: &str = "https://idp.example.com";
+
+        let key_pair = RS256KeyPair::generate(2048).unwrap();
+        let components = key_pair.public_key().to_components();
+        let jwk = |kid: &str| {
+            serde_json::json!({
+                "kid": kid,
+                "kty": "RSA",
+                "n": base64::encode_config(&components.n, base64::URL_SAFE_NO_PAD),
+                "e": base64::encode_config(&components.e, base64::URL_SAFE_NO_PAD),
+            })
+        };
+        let pem = key_pair.to_pem().unwrap();
+        let idp_token = |kid: &str| {
+            let claims = Claims::create(Duration::from_hours(1))
+                .with_issuer(IDP_ISSUER)
+                .with_subject(default_org_id().0);
+            RS256KeyPair::from_pem(&pem)
+                .unwrap()
+                .with_key_id(kid)
+                .sign(claims)
+                .unwrap()
+        };
+
+        let jwks = std::sync::Arc::new(std::sync::Mutex::new(
+            serde_json::json!({ "keys": [jwk("a")] }),
+        ));
+        let fetches = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = start_jwks_server(jwks.clone(), fetches.clone()).await;
+        let fetch_count = || fetches.load(std::sync::atomic::Ordering::SeqCst);
+
+        let cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            jwt_allowed_issuers: vec![IDP_ISSUER.to_owned()],
+            ..(*test_config()).clone()
+        });
+        let jwks_client = JwksClient::new(
+            url.clone(),
+            std::time::Duration::from_secs(60),
+            std::time::Duration::ZERO,
+        );
+        let authenticate = |client: &JwksClient, token: String| {
+            let mut req = request_parts(&cfg, Some(&token));
+            req.extensions_mut().insert(Some(client.clone()));
+            async move { Permissions::authenticate(&mut req).await }
+        };
+
+        // A known key, which is only fetched once
+        for _ in 0..2 {
+            let permissions = authenticate(&jwks_client, idp_token("a")).await.unwrap();
+            assert_eq!(permissions.org_id, default_org_id());
+            assert_eq!(permissions.type_, KeyType::Organization);
+        }
+        assert_eq!(fetch_count(), 1);
+
+        // The provider rotates its keys, revoking "a" for "b". The unknown key triggers a refresh.
+        *jwks.lock().unwrap() = serde_json::json!({ "keys": [jwk("b")] });
+        authenticate(&jwks_client, idp_token("b")).await.unwrap();
+        assert_eq!(fetch_count(), 2);
+
+        // The revoked key is rejected, even after refreshing again
+        let err = authenticate(&jwks_client, idp_token("a"))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, AuthError::BadSignature));
+        assert_eq!(fetch_count(), 3);
+
+        // Refreshes on unknown keys are rate limited
+        let rate_limited_client = JwksClient::new(
+            url,
+            std::time::Duration::from_secs(60),
+            std::time::Duration::from_secs(3600),
+        );
+        authenticate(&rate_limited_client, idp_token("b"))
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            let err = authenticate(&rate_limited_client, idp_token("c"))
+                .await
+                .err()
+                .unwrap();
+            assert!(matches!(err, AuthError::BadSignature));
+        }
+        assert_eq!(fetch_count(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_jwks_timeout() {
+        // An identity provider that never answers in time
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/jwks", listener.local_addr().unwrap());
+        let router = axum::Router::new().route(
+            "/jwks",
+            axum::routing::get(|| async {
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                axum::Json(serde_json::json!({ "keys": [] }))
+            }),
+        );
+        tokio::spawn(async move {
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service())
+                .await
+                .unwrap();
+        });
+
+        let cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            jwt_verification_timeout: 100,
+            ..(*test_config()).clone()
+        });
+        let jwks_client = JwksClient::new(
+            url,
+            std::time::Duration::from_secs(60),
+            std::time::Duration::ZERO,
+        );
+        let token = RS256KeyPair::generate(2048)
+            .unwrap()
+            .with_key_id("a")
+            .sign(Claims::create(Duration::from_hours(1)))
+            .unwrap();
+
+        // Fetching the key counts towards the verification deadline
+        let started = std::time::Instant::now();
+        let err = authenticate_idp_token(&jwks_client, &cfg, &token, "a")
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, AuthError::Timeout));
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(
+            Error::from(err).into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dev_mode() {
+        let load = |jwt_secret: Option<&str>| {
+            let mut cfg = None;
+            figment::Jail::expect_with(|jail| {
+                jail.set_env("SVIX_DEV_MODE", "true");
+                if let Some(jwt_secret) = jwt_secret {
+                    jail.set_env("SVIX_JWT_SECRET", jwt_secret);
+                }
+                cfg = Some(crate::cfg::load().unwrap());
+                Ok(())
+            });
+            cfg.unwrap()
+        };
+        let dev_token =
+            generate_org_token(&Keys::new(Keys::DEV_SECRET.as_bytes()), default_org_id()).unwrap();
+
+        // Without a configured secret, the dev token works
+        let cfg = load(None);
+        assert!(cfg.dev_mode_enabled());
+        let mut req = request_parts(&cfg, Some(&dev_token));
+        let permissions = Permissions::from_request(&mut req).await.unwrap();
+        assert_eq!(permissions.org_id, default_org_id());
+
+        // A configured secret turns dev mode off, and the dev token is rejected
+        let cfg = load(Some("8KjzRXrKkd9YFcNyqLSIY8JwiaCeRc6WK4UkMnSW"));
+        assert!(!cfg.dev_mode_enabled());
+        let mut req = request_parts(&cfg, Some(&dev_token));
+        let err = Permissions::from_request(&mut req).await.err().unwrap();
+        assert_eq!(err.into_response().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_app_scope() {
+        use tower::ServiceExt;
+
+        async fn handler(scope: AuthenticatedAppScope) -> String {
+            scope.app_id.0
+        }
+
+        // There's no database, so any lookup would fail the request
+        let cfg = test_config();
+        let router = axum::Router::new()
+            .route("/app/:app_id/", axum::routing::get(handler))
+            .layer(Extension(cfg.clone()));
+        let request = |app_id: &str, token: &str| {
+            Request::builder()
+                .uri(format!("/app/{}/", app_id))
+                .header(
+                    axum::http::header::AUTHORIZATION,
+                    format!("Bearer {}", token),
+                )
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        let app_token =
+            generate_app_token(&cfg.jwt_secret, default_org_id(), app_id.clone()).unwrap();
+
+        let resp = router
+            .clone()
+            .oneshot(request(&app_id.0, &app_token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body, app_id.0.as_bytes());
+
+        let resp = router
+            .clone()
+            .oneshot(request("app_2EhUTiNjrsCVDKRRauIWSP1dSpY", &app_token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // Organization tokens need the database to check the application, so aren't accepted
+        let org_token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let resp = router
+            .oneshot(request(&app_id.0, &org_token))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_sign_error() {
+        /// Claims which can't be serialized, so signing them fails
+        #[derive(Deserialize)]
+        struct Unserializable {}
+
+        impl Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                _serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("unserializable"))
+            }
+        }
+
+        let claims = Claims::with_custom_claims(Unserializable {}, Duration::from_hours(1));
+        assert!(matches!(
+            Keys::generate().sign(claims),
+            Err(Error::Generic(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_env() {
+        let cfg = test_config();
+        let permissions = |token: String| {
+            let cfg = cfg.clone();
+            async move {
+                Permissions::authenticate(&mut request_parts(&cfg, Some(&token)))
+                    .await
+                    .unwrap()
+            }
+        };
+        let app_token = |env| {
+            generate_app_token_for_env(
+                &cfg.jwt_secret,
+                default_org_id(),
+                ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned()),
+                env,
+            )
+            .unwrap()
+        };
+
+        // A live token can't be used in the sandbox environment
+        let live = permissions(app_token("live")).await;
+        assert_eq!(live.env.as_deref(), Some("live"));
+        assert!(live.require_env(&cfg, "live").is_ok());
+        let err = live.require_env(&cfg, "sandbox").err().unwrap();
+        assert_eq!(
+            Error::from(err).into_response().status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let sandbox = permissions(app_token("sandbox")).await;
+        assert!(sandbox.require_env(&cfg, "sandbox").is_ok());
+        assert!(sandbox.require_env(&cfg, "live").is_err());
+
+        // Tokens without the claim are for the default environment
+        let legacy =
+            permissions(generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap()).await;
+        assert_eq!(legacy.env, None);
+        assert_eq!(legacy.env(&cfg), cfg.default_environment);
+        assert!(legacy.require_env(&cfg, &cfg.default_environment).is_ok());
+        assert!(legacy.require_env(&cfg, "sandbox").is_err());
+    }
+
+    #[test]
+    fn test_permissions_display() {
+        assert_eq!(
+            Permissions::for_org(default_org_id()).to_string(),
+            "org=org_23rb8YdGqMT0qIzpgGwdXfHirMu,type=organization"
+        );
+        assert_eq!(
+            Permissions::for_app(
+                default_org_id(),
+                ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned())
+            )
+            .to_string(),
+            "org=org_23rb8YdGqMT0qIzpgGwdXfHirMu,type=application,app=app_2EhUTiNjrsCVDKRRauIWSP1dSpX"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_permissions_builder() {
+        // Handlers can be called with permissions injected directly, without any token
+        async fn handler(permissions: Permissions) -> Result<String> {
+            permissions.require_feature("beta")?;
+            Ok(permissions.to_string())
+        }
+
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        let org = Permissions::for_org(default_org_id());
+        let mut app = Permissions::for_app(default_org_id(), app_id.clone());
+        assert_eq!(org.type_, KeyType::Organization);
+        assert_eq!(app.type_, KeyType::Application);
+        assert_eq!(app.app_id(), Some(&app_id));
+
+        let err = handler(org).await.err().unwrap();
+        assert_eq!(err.into_response().status(), StatusCode::NOT_FOUND);
+
+        app.features.insert("beta".to_owned());
+        let described = handler(app).await.unwrap();
+        assert_eq!(
+            described,
+            "org=org_23rb8YdGqMT0qIzpgGwdXfHirMu,type=application,app=app_2EhUTiNjrsCVDKRRauIWSP1dSpX"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_require_app_token_scope() {
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        let permissive_cfg = test_config();
+        let strict_cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            require_app_token_scope: true,
+            ..(*permissive_cfg).clone()
+        });
+
+        let scopeless_token =
+            generate_app_token(&permissive_cfg.jwt_secret, default_org_id(), app_id.clone())
+                .unwrap();
+        let scoped_token = generate_scoped_app_token(
+            &permissive_cfg.jwt_secret,
+            default_org_id(),
+            app_id,
+            &["message:read", "message:write"],
+        )
+        .unwrap();
+        let org_token = generate_org_token(&permissive_cfg.jwt_secret, default_org_id()).unwrap();
+
+        for token in [&scopeless_token, &scoped_token, &org_token] {
+            let mut req = request_parts(&permissive_cfg, Some(token));
+            Permissions::authenticate(&mut req).await.unwrap();
+        }
+
+        let mut req = request_parts(&strict_cfg, Some(&scopeless_token));
+        let err = Permissions::authenticate(&mut req).await.err().unwrap();
+        assert!(matches!(err, AuthError::MissingClaim("scope")));
+        assert_eq!(
+            Error::from(err).into_response().status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let mut req = request_parts(&strict_cfg, Some(&scoped_token));
+        let permissions = Permissions::authenticate(&mut req).await.unwrap();
+        assert_eq!(
+            permissions.scopes,
+            HashSet::from(["message:read".to_owned(), "message:write".to_owned()])
+        );
+
+        // Organization tokens are unaffected
+        let mut req = request_parts(&strict_cfg, Some(&org_token));
+        Permissions::authenticate(&mut req).await.unwrap();
+    }
+
+    #[test]
+    fn test_check_scope() {
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        let request = |method: &str, path: &str| {
+            RequestParts::new(
+                Request::builder()
+                    .method(method)
+                    .uri(path)
+                    .extension(OriginalUri(path.parse().unwrap()))
+                    .body(())
+                    .unwrap(),
+            )
+        };
+
+        for (method, path, scope) in [
+            ("GET", "/api/v1/app/app_1/", "application:read"),
+            ("PUT", "/api/v1/app/app_1/", "application:write"),
+            (
+                "GET",
+                "/api/v1/app/app_1/verification-snippet/",
+                "application:read",
+            ),
+            ("GET", "/api/v1/app/app_1/endpoint/", "endpoint:read"),
+            (
+                "PATCH",
+                "/api/v1/app/app_1/endpoint/ep_1/",
+                "endpoint:write",
+            ),
+            ("GET", "/api/v1/app/app_1/msg/msg_1/", "message:read"),
+            ("POST", "/api/v1/app/app_1/msg/", "message:write"),
+            (
+                "HEAD",
+                "/api/v1/app/app_1/attempt/msg/msg_1/",
+                "message:read",
+            ),
+            (
+                "POST",
+                "/api/v1/app/app_1/msg/msg_1/endpoint/ep_1/resend/",
+                "message:write",
+            ),
+        ] {
+            assert_eq!(
+                required_scope(&request(method, path)),
+                scope,
+                "{} {}",
+                method,
+                path
+            );
+        }
+
+        let mut permissions = Permissions::for_app(default_org_id(), app_id);
+        permissions.scopes.insert("message:read".to_owned());
+        check_scope(&request("GET", "/api/v1/app/app_1/msg/"), &permissions).unwrap();
+        for (method, path) in [
+            ("POST", "/api/v1/app/app_1/msg/"),
+            ("GET", "/api/v1/app/app_1/endpoint/"),
+        ] {
+            let err = check_scope(&request(method, path), &permissions)
+                .err()
+                .unwrap();
+            assert_eq!(err.into_response().status(), StatusCode::FORBIDDEN);
+        }
+
+        // Unscoped application tokens and organization tokens aren't limited
+        permissions.scopes.clear();
+        check_scope(&request("POST", "/api/v1/app/app_1/msg/"), &permissions).unwrap();
+        let permissions = Permissions::for_org(default_org_id());
+        check_scope(&request("POST", "/api/v1/app/app_1/msg/"), &permissions).unwrap();
+    }
+
+    #[test]
+    fn test_generate_scoped_app_token() {
+        let cfg = test_config();
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        let generate = |scopes: &[&str]| {
+            generate_scoped_app_token(&cfg.jwt_secret, default_org_id(), app_id.clone(), scopes)
+        };
+
+        generate(APP_TOKEN_SCOPES).unwrap();
+        let invalid: [&[&str]; 3] = [&[], &["message:delete"], &["message:read", "msg:read"]];
+        for scopes in invalid {
+            assert!(matches!(generate(scopes), Err(Error::Validation(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_signing_key() {
+        let cfg = test_config();
+        check_signing_key(&cfg).await.unwrap();
+
+        // Servers for another environment than the default one still verify their own tokens
+        let sandbox_cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            environment: Some("sandbox".to_owned()),
+            ..(*cfg).clone()
+        });
+        check_signing_key(&sandbox_cfg).await.unwrap();
+
+        // Tokens are rejected before being verified when the header is too long for them
+        let short_header_cfg = std::sync::Arc::new(crate::cfg::ConfigurationInner {
+            jwt_max_header_length: 16,
+            ..(*cfg).clone()
+        });
+        let err = check_signing_key(&short_header_cfg).await.err().unwrap();
+        assert!(err.to_string().contains("header_too_large"));
+
+        // Tokens signed with another key are rejected
+        let other_key = Keys::new(b"a completely different secret");
+        let err = check_token_verifies(&cfg, &other_key).await.err().unwrap();
+        assert!(err.to_string().contains("invalid_token"));
+
+        // As are those signed with the same secret, but another algorithm
+        let other_algorithm = cfg.jwt_secret.clone().into_algorithm(JwtAlgorithm::Hs512);
+        let err = check_token_verifies(&cfg, &other_algorithm)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("invalid_token"));
+    }
+
+    #[tokio::test]
+    async fn test_message_read_token_paths() {
+        use tower::ServiceExt;
+
+        async fn handler(_permissions: Permissions) {}
+
+        let cfg = test_config();
+        let router = axum::Router::new()
+            .route("/event-type/", axum::routing::get(handler))
+            .route(
+                "/app/:app_id/msg/:msg_id/",
+                axum::routing::get(handler).delete(handler),
+            )
+            .layer(Extension(cfg.clone()));
+
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        let msg_token = generate_message_read_token(
+            &cfg.jwt_secret,
+            default_org_id(),
+            app_id.clone(),
+            MessageId("msg_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned()),
+        )
+        .unwrap();
+        let org_token = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+        let msg_path = format!("/app/{}/msg/msg_2EhUTiNjrsCVDKRRauIWSP1dSpX/", app_id);
+
+        // Handlers taking the bare `Permissions` hide everything but message paths from tokens
+        // limited to a single message
+        for (token, uri, method, status) in [
+            (&msg_token, "/event-type/", "GET", StatusCode::NOT_FOUND),
+            (&msg_token, msg_path.as_str(), "GET", StatusCode::OK),
+            (
+                &msg_token,
+                msg_path.as_str(),
+                "DELETE",
+                StatusCode::NOT_FOUND,
+            ),
+            (&org_token, "/event-type/", "GET", StatusCode::OK),
+        ] {
+            let resp = router
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header(
+                            axum::http::header::AUTHORIZATION,
+                            format!("Bearer {}", token),
+                        )
+                        .body(axum::body::Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), status, "{} {}", method, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_beta_feature() {
+        use tower::ServiceExt;
+
+        async fn beta_handler(permissions: Permissions) -> Result<()> {
+            permissions.require_feature("beta-endpoint")
+        }
+
+        let cfg = test_config();
+        let router = axum::Router::new()
+            .route("/beta/", axum::routing::get(beta_handler))
+            .layer(Extension(cfg.clone()));
+
+        let with_feature = generate_org_token_with_features(
+            &cfg.jwt_secret,
+            default_org_id(),
+            vec!["other".to_owned(), "beta-endpoint".to_owned()],
+        )
+        .unwrap();
+        let with_other_feature = generate_org_token_with_features(
+            &cfg.jwt_secret,
+            default_org_id(),
+            vec!["other".to_owned()],
+        )
+        .unwrap();
+        let without_features = generate_org_token(&cfg.jwt_secret, default_org_id()).unwrap();
+
+        for (token, status) in [
+            (with_feature, StatusCode::OK),
+            (with_other_feature, StatusCode::NOT_FOUND),
+            (without_features, StatusCode::NOT_FOUND),
+        ] {
+            let resp = router
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/beta/")
+                        .header(
+                            axum::http::header::AUTHORIZATION,
+                            format!("Bearer {}", token),
+                        )
+                        .body(axum::body::Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), status);
+        }
+    }
+
+    #[test]
+    fn test_app_id_matches() {
+        let app_id = ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpX".to_owned());
+        assert!(app_id_matches(&app_id, &app_id.clone()));
+        assert!(!app_id_matches(
+            &app_id,
+            &ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSpY".to_owned())
+        ));
+        assert!(!app_id_matches(
+            &app_id,
+            &ApplicationId("app_2EhUTiNjrsCVDKRRauIWSP1dSp".to_owned())
+        ));
+    }
+
+    #[test]
+    fn test_keys_from_base64() {
+        let secret = [7u8; Keys::MIN_SECRET_LENGTH];
+        let keys = Keys::from_base64(&base64::encode(secret)).unwrap();
+
+        // Tokens minted with the decoded key verify with a key made from the raw bytes
+        let token = generate_org_token(&keys, default_org_id()).unwrap();
+        Keys::new(&secret)
+            .key
+            .verify_token::<CustomClaim>(&token, None)
+            .unwrap();
+
+        assert!(Keys::from_base64("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_keys_from_base64_too_short() {
+        let secret = [7u8; Keys::MIN_SECRET_LENGTH - 1];
+        assert!(Keys::from_base64(&base64::encode(secret)).is_err());
+    }
+
+    #[test]
+    fn test_keys_algorithms() {
+        let secret = [7u8; Keys::RECOMMENDED_SECRET_LENGTH];
+        for algorithm in [
+            JwtAlgorithm::Hs256,
+            JwtAlgorithm::Hs384,
+            JwtAlgorithm::Hs512,
+        ] {
+            let keys = Keys::with_algorithm(&secret, algorithm);
+            assert_eq!(keys.algorithm(), algorithm);
+            let token = generate_org_token(&keys, default_org_id()).unwrap();
+            let claims = keys.key.verify_token::<CustomClaim>(&token, None).unwrap();
+            assert_eq!(claims.subject, Some(default_org_id().0));
+
+            // Tokens don't verify under another algorithm, even with the same secret
+            for other in [
+                JwtAlgorithm::Hs256,
+                JwtAlgorithm::Hs384,
+                JwtAlgorithm::Hs512,
+            ] {
+                if other != algorithm {
+                    assert!(Keys::with_algorithm(&secret, other)
+                        .key
+                        .verify_token::<CustomClaim>(&token, None)
+                        .is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_keys_generate() {
+        let keys = Keys::generate();
+        assert_eq!(keys.secret_len(), Keys::RECOMMENDED_SECRET_LENGTH);
+        let token = generate_org_token(&keys, default_org_id()).unwrap();
+        keys.key.verify_token::<CustomClaim>(&token, None).unwrap();
+
+        // Every generated key is different
+        assert!(Keys::generate()
+            .key
+            .verify_token::<CustomClaim>(&token, None)
+            .is_err());
+    }
+}
@@ -0,0 +1,127 @@
+// SPDX-FileCopyrightText: © 2022 Svix Authors
+// SPDX-License-Identifier: MIT
+
+//! Certificate pinning for endpoints with a pinned TLS fingerprint. The fingerprint is checked
+//! during the handshake, so nothing is sent to a server presenting a different certificate.
+
+use std::{sync::Arc, time::SystemTime};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+};
+
+use super::types::TlsFingerprint;
+
+/// Verifies certificates with the given verifier, and additionally requires the leaf certificate
+/// to match the pinned fingerprint
+pub struct PinnedCertVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    fingerprint: TlsFingerprint,
+}
+
+impl PinnedCertVerifier {
+    pub fn new(inner: Arc<dyn ServerCertVerifier>, fingerprint: TlsFingerprint) -> Self {
+        Self { inner, fingerprint }
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        // Checked first, as it's the cheaper check
+        if TlsFingerprint::from_certificate(&end_entity.0) != self.fingerprint {
+            return Err(rustls::Error::General(
+                "TLS certificate fingerprint mismatch".to_owned(),
+            ));
+        }
+
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )
+    }
+}
+
+/// The regular certificate verifier, trusting the Mozilla root certificates
+fn webpki_verifier() -> Arc<dyn ServerCertVerifier> {
+    let mut roots = RootCertStore::empty();
+    roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+    Arc::new(WebPkiVerifier::new(roots, None))
+}
+
+/// A TLS configuration only accepting servers presenting a certificate with the given fingerprint
+pub fn pinned_tls_config(fingerprint: TlsFingerprint) -> ClientConfig {
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier::new(
+            webpki_verifier(),
+            fingerprint,
+        )))
+        .with_no_client_auth()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct AcceptAll;
+
+    impl ServerCertVerifier for AcceptAll {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: SystemTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    fn verify(verifier: &PinnedCertVerifier, cert: &[u8]) -> Result<(), rustls::Error> {
+        verifier
+            .verify_server_cert(
+                &Certificate(cert.to_vec()),
+                &[],
+                &ServerName::try_from("example.com").unwrap(),
+                &mut std::iter::empty(),
+                &[],
+                SystemTime::now(),
+            )
+            .map(|_| ())
+    }
+
+    #[test]
+    fn test_pinned_cert_verifier() {
+        let verifier = PinnedCertVerifier::new(
+            Arc::new(AcceptAll),
+            TlsFingerprint::from_certificate(b"pinned"),
+        );
+
+        verify(&verifier, b"pinned").unwrap();
+        assert!(matches!(
+            verify(&verifier, b"other"),
+            Err(rustls::Error::General(_))
+        ));
+    }
+}
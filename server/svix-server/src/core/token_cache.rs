@@ -0,0 +1,211 @@
+// SPDX-FileCopyrightText: © 2022 Svix Authors
+// SPDX-License-Identifier: MIT
+
+//! An in-process LRU cache of verified tokens, so a token used for many requests only has its
+//! signature verified and its claims parsed once. Entries never outlive the token's expiry.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use jwt_simple::prelude::{Clock, UnixTimeStamp};
+
+use super::security::Permissions;
+
+/// Tokens are keyed by their hash so the cache doesn't hold on to the tokens themselves
+type TokenHash = [u8; 32];
+
+struct Entry {
+    permissions: Permissions,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<TokenHash, Entry>,
+    /// The entries by when they were last used, so the least recently used one is the first
+    by_last_used: BTreeMap<u64, TokenHash>,
+    /// Incremented on every access, used for ordering the entries by their last use
+    clock: u64,
+}
+
+impl Inner {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, key: &TokenHash) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.by_last_used.remove(&entry.last_used);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TokenCache {
+    inner: Arc<Mutex<Inner>>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl TokenCache {
+    /// A cache holding at most `capacity` tokens for at most `ttl` each. It's disabled when
+    /// `capacity` is 0.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner::default())),
+            capacity,
+            ttl,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// The permissions of the token, if it has been verified before and hasn't expired since
+    pub fn get(&self, token: &str) -> Option<Permissions> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let key = hmac_sha256::Hash::hash(token.as_bytes());
+        let now = Instant::now();
+
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        let clock = inner.tick();
+        match inner.entries.get_mut(&key) {
+            Some(entry) if entry.expires_at > now => {
+                inner.by_last_used.remove(&entry.last_used);
+                inner.by_last_used.insert(clock, key);
+                entry.last_used = clock;
+                Some(entry.permissions.clone())
+            }
+            _ => {
+                inner.remove(&key);
+                None
+            }
+        }
+    }
+
+    /// Caches the permissions of a verified token, until the token's expiry at the latest. The
+    /// least recently used token is evicted when the cache is full.
+    pub fn insert(
+        &self,
+        token: &str,
+        permissions: Permissions,
+        token_expires_at: Option<UnixTimeStamp>,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut expires_at = now + self.ttl;
+        if let Some(token_expires_at) = token_expires_at {
+            let remaining = token_expires_at.as_f64() - Clock::now_since_epoch().as_f64();
+            if remaining <= 0.0 {
+                return;
+            }
+            expires_at = expires_at.min(now + Duration::from_secs_f64(remaining));
+        }
+
+        let key = hmac_sha256::Hash::hash(token.as_bytes());
+
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        inner.remove(&key);
+        while inner.entries.len() >= self.capacity {
+            match inner.by_last_used.iter().next().map(|(_, key)| *key) {
+                Some(least_recently_used) => inner.remove(&least_recently_used),
+                None => break,
+            }
+        }
+
+        let last_used = inner.tick();
+        inner.by_last_used.insert(last_used, key);
+        inner.entries.insert(
+            key,
+            Entry {
+                permissions,
+                expires_at,
+                last_used,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jwt_simple::prelude::Duration as JwtDuration;
+
+    use super::*;
+    use crate::core::types::OrganizationId;
+
+    fn permissions(org_id: &str) -> Permissions {
+        Permissions::for_org(OrganizationId(org_id.to_owned()))
+    }
+
+    fn in_an_hour() -> Option<UnixTimeStamp> {
+        Some(Clock::now_since_epoch() + JwtDuration::from_hours(1))
+    }
+
+    #[test]
+    fn test_token_cache_disabled() {
+        let cache = TokenCache::new(0, Duration::from_secs(60));
+        cache.insert("token", permissions("org_1"), in_an_hour());
+        assert!(cache.get("token").is_none());
+    }
+
+    #[test]
+    fn test_token_cache_get() {
+        let cache = TokenCache::new(10, Duration::from_secs(60));
+        assert!(cache.get("token").is_none());
+
+        cache.insert("token", permissions("org_1"), in_an_hour());
+        assert_eq!(cache.get("token").unwrap().org_id.0, "org_1");
+        assert!(cache.get("other").is_none());
+
+        // Tokens without an expiry are cached for the TTL
+        cache.insert("no_exp", permissions("org_2"), None);
+        assert_eq!(cache.get("no_exp").unwrap().org_id.0, "org_2");
+    }
+
+    #[test]
+    fn test_token_cache_expiry() {
+        // Expired tokens are never cached
+        let cache = TokenCache::new(10, Duration::from_secs(60));
+        cache.insert(
+            "expired",
+            permissions("org_1"),
+            Some(Clock::now_since_epoch() - JwtDuration::from_secs(1)),
+        );
+        assert!(cache.get("expired").is_none());
+
+        // And entries are evicted after the TTL
+        let cache = TokenCache::new(10, Duration::from_millis(10));
+        cache.insert("token", permissions("org_1"), in_an_hour());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(cache.get("token").is_none());
+    }
+
+    #[test]
+    fn test_token_cache_evicts_least_recently_used() {
+        let cache = TokenCache::new(2, Duration::from_secs(60));
+        cache.insert("a", permissions("org_a"), in_an_hour());
+        cache.insert("b", permissions("org_b"), in_an_hour());
+
+        // Using `a` makes `b` the least recently used
+        assert!(cache.get("a").is_some());
+        cache.insert("c", permissions("org_c"), in_an_hour());
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+}
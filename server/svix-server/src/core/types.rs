@@ -155,6 +155,11 @@ pub trait BaseId: Deref<Target = String> {
 
     fn new(dt: Option<DateTime<Utc>>, payload: Option<&[u8]>) -> Self::Output;
 
+    /// Generates a new, random id for the current time, e.g. `org_24NVKcPqNLXKu3xQhJnw8fSumZK`
+    fn generate() -> Self::Output {
+        Self::new(None, None)
+    }
+
     fn start_id(start: DateTime<Utc>) -> Self::Output {
         let buf = [0u8; KsuidMs::PAYLOAD_BYTES];
         Self::new(Some(start), Some(&buf[..]))
@@ -411,6 +416,38 @@ impl Validate for EventTypeNameSet {
     }
 }
 
+string_wrapper!(TlsFingerprint);
+
+impl TlsFingerprint {
+    /// The SHA-256 fingerprint of a DER encoded certificate, as lowercase hex
+    pub fn from_certificate(der: &[u8]) -> Self {
+        let digest = hmac_sha256::Hash::hash(der);
+        Self(digest.iter().map(|x| format!("{:02x}", x)).collect())
+    }
+}
+
+impl Validate for TlsFingerprint {
+    fn validate(&self) -> Result<(), validator::ValidationErrors> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^[0-9a-f]{64}$").unwrap();
+        }
+        let mut errors = ValidationErrors::new();
+        if !RE.is_match(&self.0) {
+            errors.add(
+                ALL_ERROR,
+                ValidationError::new(
+                    "TLS fingerprints must be a SHA-256 digest in lowercase hex (64 characters)",
+                ),
+            );
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ExpiringSigningKeys(pub Vec<ExpiringSigningKey>);
 json_wrapper!(ExpiringSigningKeys);
@@ -635,6 +672,101 @@ impl Validate for EndpointHeadersPatch {
     }
 }
 
+/// A retry policy given on message creation which supersedes the globally configured retry
+/// schedule for the deliveries of that one message.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RetryScheduleOverride {
+    /// An explicit list of the delays (in seconds) to wait between attempts
+    Schedule(Vec<u64>),
+    /// Exponential backoff starting from `initial_interval` seconds and growing by a factor of
+    /// `multiplier` with each attempt
+    #[serde(rename_all = "camelCase")]
+    Exponential {
+        initial_interval: u64,
+        multiplier: u32,
+        max_attempts: u16,
+    },
+}
+json_wrapper!(RetryScheduleOverride);
+
+impl RetryScheduleOverride {
+    /// The maximum number of retries a schedule may contain
+    pub const MAX_ATTEMPTS: usize = 10;
+    /// The maximum delay between two attempts (in seconds), one day
+    pub const MAX_INTERVAL: u64 = 24 * 60 * 60;
+    const MAX_MULTIPLIER: u32 = 10;
+
+    /// The delays to wait between each retry, in the same format as the configured
+    /// `retry_schedule`.
+    pub fn durations(&self) -> Vec<std::time::Duration> {
+        match self {
+            Self::Schedule(schedule) => schedule
+                .iter()
+                .map(|x| std::time::Duration::from_secs(*x))
+                .collect(),
+            Self::Exponential {
+                initial_interval,
+                multiplier,
+                max_attempts,
+            } => std::iter::successors(Some(*initial_interval), |x| {
+                Some(x.saturating_mul(*multiplier as u64))
+            })
+            .take(*max_attempts as usize)
+            .map(|x| std::time::Duration::from_secs(x.min(Self::MAX_INTERVAL)))
+            .collect(),
+        }
+    }
+}
+
+impl Validate for RetryScheduleOverride {
+    fn validate(&self) -> std::result::Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        let interval_valid = |x: &u64| (1..=Self::MAX_INTERVAL).contains(x);
+        match self {
+            Self::Schedule(schedule) => {
+                if schedule.len() > Self::MAX_ATTEMPTS {
+                    errors.add(ALL_ERROR, ValidationError::new("Retry schedule too long"));
+                }
+                if !schedule.iter().all(interval_valid) {
+                    errors.add(
+                        ALL_ERROR,
+                        ValidationError::new("Retry intervals must be between 1s and 1 day"),
+                    );
+                }
+            }
+            Self::Exponential {
+                initial_interval,
+                multiplier,
+                max_attempts,
+            } => {
+                if *max_attempts as usize > Self::MAX_ATTEMPTS {
+                    errors.add(ALL_ERROR, ValidationError::new("Retry schedule too long"));
+                }
+                if !interval_valid(initial_interval) {
+                    errors.add(
+                        ALL_ERROR,
+                        ValidationError::new("Retry intervals must be between 1s and 1 day"),
+                    );
+                }
+                if !(1..=Self::MAX_MULTIPLIER).contains(multiplier) {
+                    errors.add(
+                        ALL_ERROR,
+                        ValidationError::new("Retry multiplier must be between 1 and 10"),
+                    );
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
 #[repr(i16)]
 #[derive(Clone, Debug, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
 pub enum MessageAttemptTriggerType {
@@ -651,6 +783,17 @@ pub enum MessageStatus {
     Sending = 3,
 }
 
+/// Why an attempt failed without getting a response from the endpoint
+#[repr(i16)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+pub enum AttemptFailureKind {
+    /// The TLS handshake failed, e.g. as the certificate didn't match the pinned fingerprint
+    Tls = 0,
+    Timeout = 1,
+    Connection = 2,
+    Other = 3,
+}
+
 #[repr(i16)]
 #[derive(Clone, Debug, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
 pub enum StatusCodeClass {
@@ -663,6 +806,7 @@ pub enum StatusCodeClass {
 }
 
 enum_wrapper!(MessageAttemptTriggerType);
+enum_wrapper!(AttemptFailureKind);
 enum_wrapper!(MessageStatus);
 enum_wrapper!(StatusCodeClass);
 
@@ -671,11 +815,28 @@ mod tests {
     use crate::core::types::{EventChannel, EventTypeName};
 
     use super::{
-        ApplicationId, ApplicationUid, EndpointHeaders, EndpointHeadersPatch, EndpointSecret,
+        ApplicationId, ApplicationUid, BaseId, EndpointHeaders, EndpointHeadersPatch,
+        EndpointSecret, OrganizationId, RetryScheduleOverride, TlsFingerprint,
     };
-    use std::collections::HashMap;
+    use serde_json::json;
+    use std::{collections::HashMap, time::Duration};
     use validator::Validate;
 
+    #[test]
+    fn test_id_generation() {
+        let org_id = OrganizationId::generate();
+        org_id.validate().unwrap();
+        assert!(org_id.starts_with("org_"));
+        assert_eq!(org_id.len(), "org_".len() + 27);
+
+        let app_id = ApplicationId::generate();
+        app_id.validate().unwrap();
+        assert!(app_id.starts_with("app_"));
+        assert_eq!(app_id.len(), "app_".len() + 27);
+
+        assert_ne!(ApplicationId::generate(), app_id);
+    }
+
     #[test]
     fn test_id_validation() {
         let app_id = ApplicationId("app_24NVKcPqNLXKu3xQhJnw8fSumZK".to_owned());
@@ -684,6 +845,15 @@ mod tests {
         let app_id = ApplicationId("badprefix_24NVKcPqNLXKu3xQhJnw8fSumZK".to_owned());
         assert!(app_id.validate().is_err());
 
+        // Ids of a different type are rejected
+        let org_id = OrganizationId("app_24NVKcPqNLXKu3xQhJnw8fSumZK".to_owned());
+        assert!(org_id.validate().is_err());
+        let app_id = ApplicationId("org_24NVKcPqNLXKu3xQhJnw8fSumZK".to_owned());
+        assert!(app_id.validate().is_err());
+        OrganizationId("org_24NVKcPqNLXKu3xQhJnw8fSumZK".to_owned())
+            .validate()
+            .unwrap();
+
         let app_uid = ApplicationUid("app_24NVKcPqNLXKu3xQhJnw8fSumZK".to_owned());
         assert!(app_uid.validate().is_err());
 
@@ -811,6 +981,90 @@ mod tests {
         secret.validate().unwrap();
     }
 
+    #[test]
+    fn test_tls_fingerprint() {
+        let fingerprint = TlsFingerprint::from_certificate(b"abc");
+        assert_eq!(
+            fingerprint.0,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        fingerprint.validate().unwrap();
+
+        for invalid in [
+            "",
+            "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD",
+            "ba:78:16:bf",
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015adff",
+        ] {
+            assert!(TlsFingerprint(invalid.to_owned()).validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_retry_schedule_override() {
+        let schedule: RetryScheduleOverride = serde_json::from_value(json!([1, 5, 10])).unwrap();
+        schedule.validate().unwrap();
+        assert_eq!(
+            schedule.durations(),
+            vec![
+                Duration::from_secs(1),
+                Duration::from_secs(5),
+                Duration::from_secs(10)
+            ]
+        );
+
+        let exponential: RetryScheduleOverride = serde_json::from_value(
+            json!({ "initialInterval": 2, "multiplier": 3, "maxAttempts": 4 }),
+        )
+        .unwrap();
+        exponential.validate().unwrap();
+        assert_eq!(
+            exponential.durations(),
+            vec![
+                Duration::from_secs(2),
+                Duration::from_secs(6),
+                Duration::from_secs(18),
+                Duration::from_secs(54)
+            ]
+        );
+
+        // Intervals are capped to the maximum interval
+        let exponential = RetryScheduleOverride::Exponential {
+            initial_interval: RetryScheduleOverride::MAX_INTERVAL,
+            multiplier: 10,
+            max_attempts: 2,
+        };
+        assert_eq!(
+            exponential.durations(),
+            vec![Duration::from_secs(RetryScheduleOverride::MAX_INTERVAL); 2]
+        );
+
+        for invalid in [
+            // Too long
+            RetryScheduleOverride::Schedule(vec![1; RetryScheduleOverride::MAX_ATTEMPTS + 1]),
+            // Zero or too large intervals
+            RetryScheduleOverride::Schedule(vec![0]),
+            RetryScheduleOverride::Schedule(vec![RetryScheduleOverride::MAX_INTERVAL + 1]),
+            RetryScheduleOverride::Exponential {
+                initial_interval: 1,
+                multiplier: 2,
+                max_attempts: RetryScheduleOverride::MAX_ATTEMPTS as u16 + 1,
+            },
+            RetryScheduleOverride::Exponential {
+                initial_interval: 0,
+                multiplier: 2,
+                max_attempts: 2,
+            },
+            RetryScheduleOverride::Exponential {
+                initial_interval: 1,
+                multiplier: 0,
+                max_attempts: 2,
+            },
+        ] {
+            assert!(invalid.validate().is_err());
+        }
+    }
+
     #[derive(serde::Deserialize)]
     struct EndpointSecretTestStruct {
         key: EndpointSecret,
@@ -0,0 +1,135 @@
+// SPDX-FileCopyrightText: © 2022 Svix Authors
+// SPDX-License-Identifier: MIT
+
+//! The scheme used for signing webhooks. It's shared between the worker, which signs the webhooks,
+//! and the API, which describes to receivers how to verify them.
+
+use super::types::{EndpointSecret, MessageId};
+
+/// The algorithm used for computing signatures
+pub const SIGNATURE_ALGORITHM: &str = "HMAC-SHA256";
+
+/// The version each signature is prefixed with
+pub const SIGNATURE_VERSION: &str = "v1";
+
+/// The content which is signed, with the placeholders replaced by the message ID, the unix
+/// timestamp (in seconds) of the attempt, and the raw request body.
+pub const SIGNED_CONTENT_TEMPLATE: &str = "{id}.{timestamp}.{body}";
+
+/// The names of the headers each webhook is sent with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignatureHeaderNames {
+    pub id: &'static str,
+    pub timestamp: &'static str,
+    pub signature: &'static str,
+}
+
+impl SignatureHeaderNames {
+    pub fn new(whitelabel_headers: bool) -> Self {
+        if whitelabel_headers {
+            Self {
+                id: "webhook-id",
+                timestamp: "webhook-timestamp",
+                signature: "webhook-signature",
+            }
+        } else {
+            Self {
+                id: "svix-id",
+                timestamp: "svix-timestamp",
+                signature: "svix-signature",
+            }
+        }
+    }
+}
+
+pub fn to_sign(timestamp: i64, body: &str, msg_id: &MessageId) -> String {
+    // The body is substituted last so it's never searched for placeholders
+    SIGNED_CONTENT_TEMPLATE
+        .replace("{id}", &msg_id.0)
+        .replace("{timestamp}", &timestamp.to_string())
+        .replace("{body}", body)
+}
+
+/// Sign a message
+pub fn sign_msg(
+    timestamp: i64,
+    body: &str,
+    msg_id: &MessageId,
+    endpoint_signing_keys: &[&EndpointSecret],
+) -> String {
+    let to_sign = to_sign(timestamp, body, msg_id);
+    let signatures = endpoint_signing_keys
+        .iter()
+        .map(|x| hmac_sha256::HMAC::mac(to_sign.as_bytes(), &x.0[..]));
+
+    signatures
+        .map(|x| format!("{},{}", SIGNATURE_VERSION, base64::encode(x)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Returns pseudocode describing to receivers how to verify webhooks signed with this scheme. It
+/// never contains an actual secret.
+pub fn verification_snippet(headers: &SignatureHeaderNames) -> String {
+    format!(
+        r#"// Verifying webhooks ({algorithm}, signature version {version})
+
+// 1. Read the headers sent with the webhook
+id = request.headers["{id_header}"]
+timestamp = request.headers["{timestamp_header}"]
+signatures = request.headers["{signature_header}"].split(" ")
+
+// 2. Build the signed content from the *raw* request body, replacing {{id}}, {{timestamp}} and {{body}}
+signed_content = "{signed_content}"
+
+// 3. Compute the expected signature with your endpoint secret: drop its "whsec_" prefix and base64 decode the rest
+key = base64_decode(endpoint_secret.strip_prefix("whsec_"))
+expected = "{version}," + base64_encode({algorithm}(key, signed_content))
+
+// 4. Accept the webhook if any of the signatures matches, comparing in constant time
+if not any(constant_time_equals(expected, s) for s in signatures):
+    reject()
+
+// 5. Reject timestamps too far from the current time to prevent replay attacks
+if abs(now() - int(timestamp)) > 5 minutes:
+    reject()
+"#,
+        algorithm = SIGNATURE_ALGORITHM,
+        version = SIGNATURE_VERSION,
+        id_header = headers.id,
+        timestamp_header = headers.timestamp,
+        signature_header = headers.signature,
+        signed_content = SIGNED_CONTENT_TEMPLATE,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_sign_matches_template() {
+        let msg_id = MessageId("msg_p5jXN8AQM9LWM0D4loKWxJek".to_owned());
+        // Bodies containing placeholders are left alone
+        let body = r#"{"test": "{id}.{timestamp}"}"#;
+
+        assert_eq!(
+            to_sign(1614265330, body, &msg_id),
+            format!("{}.{}.{}", msg_id, 1614265330, body)
+        );
+    }
+
+    #[test]
+    fn test_verification_snippet() {
+        for whitelabel_headers in [false, true] {
+            let headers = SignatureHeaderNames::new(whitelabel_headers);
+            let snippet = verification_snippet(&headers);
+
+            assert!(snippet.contains(&format!("\"{}\"", headers.id)));
+            assert!(snippet.contains(&format!("\"{}\"", headers.timestamp)));
+            assert!(snippet.contains(&format!("\"{}\"", headers.signature)));
+            assert!(snippet.contains(SIGNED_CONTENT_TEMPLATE));
+            assert!(snippet.contains(SIGNATURE_ALGORITHM));
+        }
+    }
+}
@@ -3,7 +3,7 @@
 
 use crate::core::types::{
     ApplicationId, BaseId, EndpointHeaders, EndpointId, EndpointIdOrUid, EndpointSecret,
-    EndpointUid, EventChannelSet, EventTypeNameSet, ExpiringSigningKeys,
+    EndpointUid, EventChannelSet, EventTypeNameSet, ExpiringSigningKeys, TlsFingerprint,
 };
 use chrono::Utc;
 use sea_orm::ActiveValue::Set;
@@ -30,6 +30,10 @@ pub struct Model {
     pub old_keys: Option<ExpiringSigningKeys>,
     pub channels: Option<EventChannelSet>,
     pub headers: Option<EndpointHeaders>,
+    /// When set, the endpoint is being deleted and only its pending deliveries are still attempted
+    /// until this deadline.
+    pub drain_deadline: Option<DateTimeWithTimeZone>,
+    pub pinned_tls_fingerprint: Option<TlsFingerprint>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -78,6 +82,12 @@ impl ActiveModelBehavior for ActiveModel {
 
 impl Entity {
     pub fn secure_find(app_id: ApplicationId) -> Select<Entity> {
+        Self::secure_find_with_draining(app_id).filter(Column::DrainDeadline.is_null())
+    }
+
+    /// Like [`Entity::secure_find`], but also includes endpoints which are being deleted but are
+    /// still draining their pending deliveries.
+    pub fn secure_find_with_draining(app_id: ApplicationId) -> Select<Entity> {
         Self::find()
             .filter(Column::AppId.eq(app_id))
             .filter(Column::Deleted.eq(false))
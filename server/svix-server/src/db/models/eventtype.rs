@@ -18,6 +18,8 @@ pub struct Model {
     pub deleted: bool,
     pub schemas: Option<Json>,
     pub name: EventTypeName,
+    pub max_payload_depth: Option<i32>,
+    pub max_payload_array_length: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
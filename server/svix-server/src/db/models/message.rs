@@ -3,7 +3,7 @@
 
 use crate::core::types::{
     ApplicationId, BaseId, EventChannelSet, EventTypeName, MessageId, MessageIdOrUid, MessageUid,
-    OrganizationId,
+    OrganizationId, RetryScheduleOverride,
 };
 use chrono::Utc;
 use sea_orm::ActiveValue::Set;
@@ -23,6 +23,8 @@ pub struct Model {
     pub payload: Option<Json>,
     pub channels: Option<EventChannelSet>,
     pub expiration: DateTimeWithTimeZone,
+    pub retry_schedule: Option<RetryScheduleOverride>,
+    pub no_matching_endpoints: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -6,8 +6,8 @@ use sea_orm::entity::prelude::*;
 use sea_orm::ActiveValue::Set;
 
 use crate::core::types::{
-    BaseId, EndpointId, MessageAttemptId, MessageAttemptTriggerType, MessageEndpointId, MessageId,
-    MessageStatus,
+    AttemptFailureKind, BaseId, EndpointId, MessageAttemptId, MessageAttemptTriggerType,
+    MessageEndpointId, MessageId, MessageStatus, TlsFingerprint,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
@@ -26,6 +26,11 @@ pub struct Model {
     pub response: String,
     pub ended_at: Option<DateTimeWithTimeZone>,
     pub trigger_type: MessageAttemptTriggerType,
+    pub custom_retry_schedule: bool,
+    /// The SHA-256 fingerprint of the certificate the endpoint presented, for https endpoints
+    pub tls_fingerprint: Option<TlsFingerprint>,
+    /// Why the attempt failed, if it did so without a response
+    pub failure_kind: Option<AttemptFailureKind>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
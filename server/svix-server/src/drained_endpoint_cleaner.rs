@@ -0,0 +1,55 @@
+// SPDX-FileCopyrightText: © 2022 Svix Authors
+// SPDX-License-Identifier: MIT
+
+use crate::core::types::MessageStatus;
+use crate::db::models::{endpoint, messagedestination};
+use crate::error::Result;
+use chrono::Utc;
+use sea_orm::{entity::prelude::*, ActiveValue::Set, DatabaseConnection, EntityTrait};
+use tokio::time::sleep;
+
+/// Removes draining endpoints which either have no pending deliveries left or whose drain
+/// deadline has passed
+pub async fn clean_drained_endpoints(pool: &DatabaseConnection) -> Result<()> {
+    let draining = endpoint::Entity::find()
+        .filter(endpoint::Column::Deleted.eq(false))
+        .filter(endpoint::Column::DrainDeadline.is_not_null())
+        .all(pool)
+        .await?;
+
+    for endp in draining {
+        let deadline = match endp.drain_deadline {
+            Some(deadline) => deadline,
+            None => continue,
+        };
+
+        if deadline.with_timezone(&Utc) > Utc::now() {
+            let pending = messagedestination::Entity::secure_find_by_endpoint(endp.id.clone())
+                .filter(
+                    messagedestination::Column::Status
+                        .is_in([MessageStatus::Pending, MessageStatus::Sending]),
+                )
+                .one(pool)
+                .await?;
+            if pending.is_some() {
+                continue;
+            }
+        }
+
+        let mut endp: endpoint::ActiveModel = endp.into();
+        endp.deleted = Set(true);
+        endp.update(pool).await?;
+    }
+
+    Ok(())
+}
+
+/// Runs every minute
+pub async fn drained_endpoint_cleaner_loop(pool: &DatabaseConnection) -> Result<()> {
+    loop {
+        sleep(std::time::Duration::from_secs(60)).await;
+        if let Err(err) = clean_drained_endpoints(pool).await {
+            tracing::error!("{}", err)
+        }
+    }
+}
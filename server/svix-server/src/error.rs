@@ -4,6 +4,7 @@
 use std::error;
 use std::fmt;
 
+use axum::http::{header, HeaderValue};
 use axum::response::IntoResponse;
 use axum::response::Response;
 use axum::Json;
@@ -121,10 +122,20 @@ pub struct ValidationErrorItem {
     pub ty: String,
 }
 
+/// The `WWW-Authenticate` challenge (RFC 6750) sent along with 401 responses
+#[derive(Debug, Clone)]
+enum AuthChallenge {
+    /// A bare `Bearer` challenge, for requests without usable credentials
+    Bearer,
+    /// A `Bearer` challenge with the given `error` parameter
+    BearerError(&'static str),
+}
+
 #[derive(Debug, Clone)]
 pub struct HttpError {
     status: StatusCode,
     body: HttpErrorBody,
+    auth_challenge: Option<AuthChallenge>,
 }
 
 impl HttpError {
@@ -132,6 +143,7 @@ impl HttpError {
         Self {
             status,
             body: HttpErrorBody::Standard { code, detail },
+            auth_challenge: None,
         }
     }
 
@@ -157,6 +169,21 @@ impl HttpError {
             code.unwrap_or_else(|| "authentication_failed".to_owned()),
             detail.unwrap_or_else(|| "Incorrect authentication credentials.".to_owned()),
         )
+        .with_auth_error("invalid_token")
+    }
+
+    /// Sets the `error` parameter of the `WWW-Authenticate: Bearer` header (RFC 6750) sent with
+    /// this error, e.g. "invalid_token" or "expired_token".
+    pub fn with_auth_error(mut self, auth_error: &'static str) -> Self {
+        self.auth_challenge = Some(AuthChallenge::BearerError(auth_error));
+        self
+    }
+
+    /// Sends a bare `WWW-Authenticate: Bearer` header with this error, without an `error`
+    /// parameter, e.g. when the request didn't use the `Bearer` scheme at all.
+    pub fn with_bare_auth_challenge(mut self) -> Self {
+        self.auth_challenge = Some(AuthChallenge::Bearer);
+        self
     }
 
     pub fn permission_denied(code: Option<String>, detail: Option<String>) -> Self {
@@ -167,6 +194,14 @@ impl HttpError {
         )
     }
 
+    pub fn too_many_requests(code: Option<String>, detail: Option<String>) -> Self {
+        Self::new_standard(
+            StatusCode::TOO_MANY_REQUESTS,
+            code.unwrap_or_else(|| "too_many_requests".to_owned()),
+            detail.unwrap_or_else(|| "Too many requests.".to_owned()),
+        )
+    }
+
     pub fn conflict(code: Option<String>, detail: Option<String>) -> Self {
         Self::new_standard(
             StatusCode::CONFLICT,
@@ -179,6 +214,7 @@ impl HttpError {
         Self {
             status: StatusCode::UNPROCESSABLE_ENTITY,
             body: HttpErrorBody::Validation { detail },
+            auth_challenge: None,
         }
     }
 
@@ -190,6 +226,14 @@ impl HttpError {
         )
     }
 
+    pub fn service_unavailable(code: Option<String>, detail: Option<String>) -> Self {
+        Self::new_standard(
+            StatusCode::SERVICE_UNAVAILABLE,
+            code.unwrap_or_else(|| "service_unavailable".to_owned()),
+            detail.unwrap_or_else(|| "The service is temporarily unavailable.".to_owned()),
+        )
+    }
+
     pub fn not_implemented(code: Option<String>, detail: Option<String>) -> Self {
         Self::new_standard(
             StatusCode::NOT_IMPLEMENTED,
@@ -229,6 +273,17 @@ impl fmt::Display for HttpError {
 
 impl IntoResponse for HttpError {
     fn into_response(self) -> Response {
-        (self.status, Json(self.body)).into_response()
+        let mut res = (self.status, Json(self.body)).into_response();
+        let challenge = match self.auth_challenge {
+            Some(AuthChallenge::Bearer) => Some("Bearer".to_owned()),
+            Some(AuthChallenge::BearerError(auth_error)) => {
+                Some(format!("Bearer error=\"{}\"", auth_error))
+            }
+            None => None,
+        };
+        if let Some(Ok(value)) = challenge.map(|c| HeaderValue::from_str(&c)) {
+            res.headers_mut().insert(header::WWW_AUTHENTICATE, value);
+        }
+        res
     }
 }
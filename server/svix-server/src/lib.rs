@@ -10,6 +10,7 @@ use cfg::CacheType;
 use std::{
     net::{SocketAddr, TcpListener},
     str::FromStr,
+    sync::Arc,
 };
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
@@ -18,19 +19,26 @@ use tower_http::trace::TraceLayer;
 use crate::{
     cfg::Configuration,
     core::{
+        auth_audit::{NoopAuthAuditSink, SharedAuthAuditSink},
         cache,
         idempotency::IdempotencyService,
+        jwks::JwksClient,
         operational_webhooks::OperationalWebhookSenderInner,
         otel_spans::{AxumOtelOnFailure, AxumOtelOnResponse, AxumOtelSpanCreator},
+        security::check_signing_key,
+        token_cache::TokenCache,
     },
     db::init_db,
+    drained_endpoint_cleaner::drained_endpoint_cleaner_loop,
     expired_message_cleaner::expired_message_cleaner_loop,
+    v1::endpoints::health::SigningKeyCheck,
     worker::worker_loop,
 };
 
 pub mod cfg;
 pub mod core;
 pub mod db;
+pub mod drained_endpoint_cleaner;
 pub mod error;
 pub mod expired_message_cleaner;
 pub mod queue;
@@ -40,16 +48,25 @@ pub mod worker;
 
 #[tracing::instrument(name = "app_start", level = "trace", skip_all)]
 pub async fn run(cfg: Configuration, listener: Option<TcpListener>) {
-    run_with_prefix(None, cfg, listener).await
+    run_with_prefix(None, cfg, listener, Arc::new(NoopAuthAuditSink)).await
 }
 
 // Made public for the purpose of E2E testing in which a queue prefix is necessary to avoid tests
-// consuming from each others' queues
+// consuming from each others' queues, and for deployments recording authentication decisions with
+// their own `AuthAuditSink`
 pub async fn run_with_prefix(
     prefix: Option<String>,
     cfg: Configuration,
     listener: Option<TcpListener>,
+    auth_audit_sink: SharedAuthAuditSink,
 ) {
+    // An instance which can't authenticate any request is of no use, so it reports itself as not
+    // ready rather than being routed traffic
+    let signing_key_check = check_signing_key(&cfg).await;
+    if let Err(e) = &signing_key_check {
+        tracing::error!("{}", e);
+    }
+
     let pool = init_db(&cfg).await;
 
     let redis_dsn = || {
@@ -69,7 +86,9 @@ pub async fn run_with_prefix(
             let mgr = crate::redis::new_redis_pool_clustered(redis_dsn(), &cfg).await;
             cache::redis::new(mgr)
         }
-        CacheType::Memory => cache::memory::new(),
+        CacheType::Memory => cache::memory::new_with_prune_interval(
+            std::time::Duration::from_secs(cfg.cache_prune_interval),
+        ),
         CacheType::None => cache::none::new(),
     };
 
@@ -78,9 +97,23 @@ pub async fn run_with_prefix(
 
     let op_webhook_sender = OperationalWebhookSenderInner::new(
         cfg.jwt_secret.clone(),
+        cfg.management_org_id.clone(),
         cfg.operational_webhook_address.clone(),
     );
 
+    let token_cache = TokenCache::new(
+        cfg.jwt_cache_size,
+        std::time::Duration::from_secs(cfg.jwt_cache_ttl),
+    );
+
+    let jwks_client = cfg.jwks_url.clone().map(|url| {
+        JwksClient::new(
+            url,
+            std::time::Duration::from_secs(cfg.jwks_cache_ttl),
+            std::time::Duration::from_secs(cfg.jwks_min_refresh_interval),
+        )
+    });
+
     // build our application with a route
     let app = Router::new()
         .nest("/api/v1", v1::router())
@@ -108,26 +141,30 @@ pub async fn run_with_prefix(
         .layer(Extension(queue_tx.clone()))
         .layer(Extension(cfg.clone()))
         .layer(Extension(cache.clone()))
-        .layer(Extension(op_webhook_sender.clone()));
+        .layer(Extension(op_webhook_sender.clone()))
+        .layer(Extension(token_cache))
+        .layer(Extension(jwks_client))
+        .layer(Extension(auth_audit_sink))
+        .layer(Extension(SigningKeyCheck(signing_key_check)));
 
     let with_api = cfg.api_enabled;
     let with_worker = cfg.worker_enabled;
 
     let listen_address =
         SocketAddr::from_str(&cfg.listen_address).expect("Error parsing server listen address");
-    let (server, worker_loop, expired_message_cleaner_loop) = tokio::join!(
+    let (server, worker_loop, expired_message_cleaner_loop, drained_endpoint_cleaner_loop) = tokio::join!(
         async {
             if with_api {
                 if let Some(l) = listener {
                     tracing::debug!("API: Listening on {}", l.local_addr().unwrap());
                     axum::Server::from_tcp(l)
                         .expect("Error starting http server")
-                        .serve(app.into_make_service())
+                        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                         .await
                 } else {
                     tracing::debug!("API: Listening on {}", listen_address);
                     axum::Server::bind(&listen_address)
-                        .serve(app.into_make_service())
+                        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                         .await
                 }
             } else {
@@ -152,11 +189,21 @@ pub async fn run_with_prefix(
                 tracing::debug!("Expired message cleaner: off");
                 Ok(())
             }
+        },
+        async {
+            if with_worker {
+                tracing::debug!("Drained endpoint cleaner: Initializing");
+                drained_endpoint_cleaner_loop(&pool).await
+            } else {
+                tracing::debug!("Drained endpoint cleaner: off");
+                Ok(())
+            }
         }
     );
     server.expect("Error initializing server");
     worker_loop.expect("Error initializing worker");
-    expired_message_cleaner_loop.expect("Error initializing expired message cleaner")
+    expired_message_cleaner_loop.expect("Error initializing expired message cleaner");
+    drained_endpoint_cleaner_loop.expect("Error initializing drained endpoint cleaner")
 }
 
 mod docs {
@@ -150,6 +150,16 @@ async fn main() {
         }
     };
 
+    for warning in cfg.warnings() {
+        tracing::warn!("{}", warning);
+    }
+
+    if cfg.dev_mode_enabled() {
+        let token =
+            generate_org_token(&cfg.jwt_secret, default_org_id()).expect("Error generating token");
+        tracing::info!("Dev mode token (Bearer): {}", token);
+    }
+
     if args.run_migrations {
         db::run_migrations(&cfg).await;
         tracing::debug!("Migrations: success");
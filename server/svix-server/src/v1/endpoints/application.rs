@@ -2,12 +2,18 @@
 // SPDX-License-Identifier: MIT
 
 use crate::{
+    cfg::Configuration,
     core::{
+        cache::Cache,
         security::{
-            AuthenticatedApplication, AuthenticatedOrganization,
+            invalidate_cached_app, AuthenticatedApplication, AuthenticatedOrganization,
             AuthenticatedOrganizationWithApplication,
         },
         types::{ApplicationId, ApplicationUid},
+        webhook_signature::{
+            verification_snippet, SignatureHeaderNames, SIGNATURE_ALGORITHM, SIGNATURE_VERSION,
+            SIGNED_CONTENT_TEMPLATE,
+        },
     },
     db::models::application,
     error::{HttpError, Result},
@@ -159,33 +165,71 @@ async fn get_application(
 
 async fn update_application(
     Extension(ref db): Extension<DatabaseConnection>,
+    Extension(ref cache): Extension<Cache>,
     ValidatedJson(data): ValidatedJson<ApplicationIn>,
     AuthenticatedOrganizationWithApplication {
         permissions: _,
         app,
     }: AuthenticatedOrganizationWithApplication,
 ) -> Result<Json<ApplicationOut>> {
-    let mut app: application::ActiveModel = app.into();
-    data.update_model(&mut app);
+    let mut model: application::ActiveModel = app.clone().into();
+    data.update_model(&mut model);
 
-    let ret = app.update(db).await?;
+    let ret = model.update(db).await?;
+    invalidate_cached_app(cache, &app).await;
     Ok(Json(ret.into()))
 }
 
 async fn delete_application(
     Extension(ref db): Extension<DatabaseConnection>,
+    Extension(ref cache): Extension<Cache>,
     AuthenticatedOrganizationWithApplication {
         permissions: _,
         app,
     }: AuthenticatedOrganizationWithApplication,
 ) -> Result<(StatusCode, Json<EmptyResponse>)> {
-    let mut app: application::ActiveModel = app.into();
-    app.deleted = Set(true);
-    app.uid = Set(None); // We don't want deleted UIDs to clash
-    app.update(db).await?;
+    let mut model: application::ActiveModel = app.clone().into();
+    model.deleted = Set(true);
+    model.uid = Set(None); // We don't want deleted UIDs to clash
+    model.update(db).await?;
+    invalidate_cached_app(cache, &app).await;
     Ok((StatusCode::NO_CONTENT, Json(EmptyResponse {})))
 }
 
+/// Describes how receivers can verify the webhooks sent for an application
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationSnippetOut {
+    pub id_header: String,
+    pub timestamp_header: String,
+    pub signature_header: String,
+    /// The content which is signed, with `{id}`, `{timestamp}` and `{body}` as placeholders
+    pub signed_content: String,
+    pub algorithm: String,
+    pub signature_version: String,
+    /// Pseudocode for verifying webhooks, ready to be adapted by receivers
+    pub snippet: String,
+}
+
+async fn get_verification_snippet(
+    Extension(ref cfg): Extension<Configuration>,
+    AuthenticatedApplication {
+        permissions: _,
+        app: _,
+    }: AuthenticatedApplication,
+) -> Result<Json<VerificationSnippetOut>> {
+    let headers = SignatureHeaderNames::new(cfg.whitelabel_headers);
+    Ok(Json(VerificationSnippetOut {
+        id_header: headers.id.to_owned(),
+        timestamp_header: headers.timestamp.to_owned(),
+        signature_header: headers.signature.to_owned(),
+        signed_content: SIGNED_CONTENT_TEMPLATE.to_owned(),
+        algorithm: SIGNATURE_ALGORITHM.to_owned(),
+        signature_version: SIGNATURE_VERSION.to_owned(),
+        snippet: verification_snippet(&headers),
+    }))
+}
+
 pub fn router() -> Router {
     Router::new()
         .route("/app/", post(create_application).get(list_applications))
@@ -195,6 +239,10 @@ pub fn router() -> Router {
                 .put(update_application)
                 .delete(delete_application),
         )
+        .route(
+            "/app/:app_id/verification-snippet/",
+            get(get_verification_snippet),
+        )
 }
 
 #[cfg(test)]
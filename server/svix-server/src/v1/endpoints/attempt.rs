@@ -5,9 +5,9 @@ use crate::{
     core::{
         security::AuthenticatedApplication,
         types::{
-            ApplicationIdOrUid, EndpointId, EndpointIdOrUid, EventChannel, EventTypeNameSet,
-            MessageAttemptId, MessageAttemptTriggerType, MessageEndpointId, MessageId,
-            MessageIdOrUid, MessageStatus, StatusCodeClass,
+            ApplicationIdOrUid, AttemptFailureKind, EndpointId, EndpointIdOrUid, EventChannel,
+            EventTypeNameSet, MessageAttemptId, MessageAttemptTriggerType, MessageEndpointId,
+            MessageId, MessageIdOrUid, MessageStatus, StatusCodeClass, TlsFingerprint,
         },
     },
     db::models::{endpoint, message, messagedestination},
@@ -47,6 +47,12 @@ pub struct MessageAttemptOut {
     pub trigger_type: MessageAttemptTriggerType,
     pub msg_id: MessageId,
     pub endpoint_id: EndpointId,
+    /// Whether this attempt was scheduled using the message's own retry schedule
+    pub custom_retry_schedule: bool,
+    /// The SHA-256 fingerprint of the certificate the endpoint presented, for https endpoints
+    pub tls_fingerprint: Option<TlsFingerprint>,
+    /// Why the attempt failed, if it did so without a response
+    pub failure_kind: Option<AttemptFailureKind>,
 
     pub id: MessageAttemptId,
 
@@ -64,6 +70,9 @@ impl From<messageattempt::Model> for MessageAttemptOut {
             trigger_type: model.trigger_type,
             msg_id: model.msg_id,
             endpoint_id: model.endp_id,
+            custom_retry_schedule: model.custom_retry_schedule,
+            tls_fingerprint: model.tls_fingerprint,
+            failure_kind: model.failure_kind,
 
             id: model.id,
             created_at: model.created_at.into(),
@@ -369,17 +378,15 @@ async fn list_attempts_by_msg(
         after,
     }): ValidatedQuery<ListAttemptsByMsgQueryParameters>,
     Path((_app_id, msg_id)): Path<(ApplicationIdOrUid, MessageId)>,
-    AuthenticatedApplication {
-        permissions: _,
-        app,
-    }: AuthenticatedApplication,
+    AuthenticatedApplication { permissions, app }: AuthenticatedApplication,
 ) -> Result<Json<ListResponse<MessageAttemptOut>>> {
     let PaginationLimit(limit) = pagination.limit;
     // Confirm message ID belongs to the given application
-    if message::Entity::secure_find_by_id(app.id.clone(), msg_id.clone())
-        .one(db)
-        .await?
-        .is_none()
+    if !permissions.allows_message(&msg_id)
+        || message::Entity::secure_find_by_id(app.id.clone(), msg_id.clone())
+            .one(db)
+            .await?
+            .is_none()
     {
         return Err(Error::Http(HttpError::not_found(None, None)));
     }
@@ -458,10 +465,7 @@ async fn list_attempted_destinations(
     Extension(ref db): Extension<DatabaseConnection>,
     ValidatedQuery(mut pagination): ValidatedQuery<Pagination<EndpointId>>,
     Path((_app_id, msg_id)): Path<(ApplicationIdOrUid, MessageIdOrUid)>,
-    AuthenticatedApplication {
-        permissions: _,
-        app,
-    }: AuthenticatedApplication,
+    AuthenticatedApplication { permissions, app }: AuthenticatedApplication,
 ) -> Result<Json<ListResponse<MessageEndpointOut>>> {
     let PaginationLimit(limit) = pagination.limit;
     let iterator = pagination.iterator.take();
@@ -472,6 +476,7 @@ async fn list_attempted_destinations(
         message::Entity::secure_find_by_id_or_uid(app.id.clone(), msg_id.clone())
             .one(db)
             .await?
+            .filter(|msg| permissions.allows_message(&msg.id))
     {
         message.id
     } else {
@@ -568,15 +573,13 @@ async fn list_messageattempts(
     }): ValidatedQuery<AttemptListFetchOptions>,
     list_filter: MessageListFetchOptions,
     Path((_app_id, msg_id)): Path<(ApplicationIdOrUid, MessageIdOrUid)>,
-    AuthenticatedApplication {
-        permissions: _,
-        app,
-    }: AuthenticatedApplication,
+    AuthenticatedApplication { permissions, app }: AuthenticatedApplication,
 ) -> Result<Json<ListResponse<MessageAttemptOut>>> {
     let PaginationLimit(limit) = pagination.limit;
     let msg = message::Entity::secure_find_by_id_or_uid(app.id.clone(), msg_id)
         .one(db)
         .await?
+        .filter(|msg| permissions.allows_message(&msg.id))
         .ok_or_else(|| HttpError::not_found(None, None))?;
 
     let mut query = messageattempt::Entity::secure_find_by_msg(msg.id);
@@ -630,14 +633,12 @@ async fn get_messageattempt(
         MessageIdOrUid,
         MessageAttemptId,
     )>,
-    AuthenticatedApplication {
-        permissions: _,
-        app,
-    }: AuthenticatedApplication,
+    AuthenticatedApplication { permissions, app }: AuthenticatedApplication,
 ) -> Result<Json<MessageAttemptOut>> {
     let msg = message::Entity::secure_find_by_id_or_uid(app.id, msg_id)
         .one(db)
         .await?
+        .filter(|msg| permissions.allows_message(&msg.id))
         .ok_or_else(|| HttpError::not_found(None, None))?;
 
     let attempt = messageattempt::Entity::secure_find_by_msg(msg.id)
@@ -4,10 +4,13 @@ use axum::{
     extract::{Extension, Path},
     Json,
 };
+use chrono::{Duration, Utc};
 use hyper::StatusCode;
 use sea_orm::{entity::prelude::*, ActiveValue::Set, QueryOrder};
 use sea_orm::{ActiveModelTrait, DatabaseConnection, QuerySelect};
+use serde::Deserialize;
 use url::Url;
+use validator::Validate;
 
 use super::{EndpointIn, EndpointOut};
 use crate::{
@@ -149,10 +152,20 @@ pub(super) async fn update_endpoint(
     Ok(Json(ret.into()))
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct DeleteEndpointQueryParams {
+    /// Let the endpoint's pending deliveries finish (for up to `endpoint_drain_timeout`) before
+    /// it's removed, instead of deleting it right away
+    #[serde(default)]
+    drain: bool,
+}
+
 pub(super) async fn delete_endpoint(
     Extension(ref db): Extension<DatabaseConnection>,
+    Extension(ref cfg): Extension<Configuration>,
     Extension(op_webhooks): Extension<OperationalWebhookSender>,
     Path((_app_id, endp_id)): Path<(ApplicationIdOrUid, EndpointIdOrUid)>,
+    ValidatedQuery(DeleteEndpointQueryParams { drain }): ValidatedQuery<DeleteEndpointQueryParams>,
     AuthenticatedApplication { permissions, app }: AuthenticatedApplication,
 ) -> Result<(StatusCode, Json<EmptyResponse>)> {
     let endp = endpoint::Entity::secure_find_by_id_or_uid(app.id.clone(), endp_id)
@@ -165,7 +178,14 @@ pub(super) async fn delete_endpoint(
     let endpoint_uid = endp.uid.clone();
 
     let mut endp: endpoint::ActiveModel = endp.into();
-    endp.deleted = Set(true);
+    if drain {
+        // Stop routing new messages to the endpoint, but let its pending deliveries finish. The
+        // endpoint is then removed by the drained endpoint cleaner.
+        let deadline = Utc::now() + Duration::seconds(cfg.endpoint_drain_timeout.into());
+        endp.drain_deadline = Set(Some(deadline.into()));
+    } else {
+        endp.deleted = Set(true);
+    }
     endp.uid = Set(None); // We don't want deleted UIDs to clash
     endp.update(db).await?;
 
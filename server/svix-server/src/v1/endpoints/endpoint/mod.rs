@@ -10,7 +10,7 @@ use crate::{
         security::AuthenticatedApplication,
         types::{
             ApplicationIdOrUid, BaseId, EndpointId, EndpointIdOrUid, EndpointUid, EventChannelSet,
-            EventTypeNameSet, MessageEndpointId, MessageStatus,
+            EventTypeNameSet, MessageEndpointId, MessageStatus, TlsFingerprint,
         },
     },
     db::models::messagedestination,
@@ -116,6 +116,12 @@ pub struct EndpointIn {
     #[serde(rename = "secret")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub key: Option<EndpointSecret>,
+
+    /// Only deliver to the endpoint if it presents a TLS certificate with this SHA-256 fingerprint
+    #[validate]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned_tls_fingerprint: Option<TlsFingerprint>,
 }
 
 // FIXME: This can and should be a derive macro
@@ -131,6 +137,7 @@ impl ModelIn for EndpointIn {
         model.disabled = Set(self.disabled);
         model.event_types_ids = Set(self.event_types_ids);
         model.channels = Set(self.channels);
+        model.pinned_tls_fingerprint = Set(self.pinned_tls_fingerprint);
         if let Some(key) = self.key {
             model.key = Set(key);
         }
@@ -150,6 +157,7 @@ pub struct EndpointOut {
     #[serde(rename = "filterTypes")]
     pub event_types_ids: Option<EventTypeNameSet>,
     pub channels: Option<EventChannelSet>,
+    pub pinned_tls_fingerprint: Option<TlsFingerprint>,
 
     pub id: EndpointId,
     pub created_at: DateTime<Utc>,
@@ -168,6 +176,7 @@ impl From<endpoint::Model> for EndpointOut {
             disabled: model.disabled,
             event_types_ids: model.event_types_ids,
             channels: model.channels,
+            pinned_tls_fingerprint: model.pinned_tls_fingerprint,
 
             id: model.id,
             created_at: model.created_at.into(),
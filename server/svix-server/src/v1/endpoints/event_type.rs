@@ -35,6 +35,14 @@ pub struct EventTypeIn {
     #[serde(default, rename = "archived")]
     pub deleted: bool,
     pub schemas: Option<serde_json::Value>,
+    /// The maximum nesting depth of objects and arrays allowed in payloads of this type
+    #[validate(range(min = 1))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_payload_depth: Option<u16>,
+    /// The maximum number of items allowed in any array in payloads of this type
+    #[validate(range(min = 1))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_payload_array_length: Option<u16>,
 }
 
 // FIXME: This can and should be a derive macro
@@ -46,6 +54,8 @@ impl ModelIn for EventTypeIn {
         model.description = Set(self.description);
         model.deleted = Set(self.deleted);
         model.schemas = Set(self.schemas);
+        model.max_payload_depth = Set(self.max_payload_depth.map(Into::into));
+        model.max_payload_array_length = Set(self.max_payload_array_length.map(Into::into));
     }
 }
 
@@ -57,6 +67,12 @@ struct EventTypeUpdate {
     #[serde(default, rename = "archived")]
     deleted: bool,
     schemas: Option<serde_json::Value>,
+    #[validate(range(min = 1))]
+    #[serde(default)]
+    max_payload_depth: Option<u16>,
+    #[validate(range(min = 1))]
+    #[serde(default)]
+    max_payload_array_length: Option<u16>,
 }
 
 // FIXME: This can and should be a derive macro
@@ -67,6 +83,8 @@ impl ModelIn for EventTypeUpdate {
         model.description = Set(self.description);
         model.deleted = Set(self.deleted);
         model.schemas = Set(self.schemas);
+        model.max_payload_depth = Set(self.max_payload_depth.map(Into::into));
+        model.max_payload_array_length = Set(self.max_payload_array_length.map(Into::into));
     }
 }
 
@@ -78,6 +96,10 @@ pub struct EventTypeOut {
     #[serde(rename = "archived")]
     pub deleted: bool,
     pub schemas: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_payload_depth: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_payload_array_length: Option<u16>,
 
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -100,6 +122,8 @@ impl From<eventtype::Model> for EventTypeOut {
             description: model.description,
             deleted: model.deleted,
             schemas: model.schemas,
+            max_payload_depth: model.max_payload_depth.map(|x| x as u16),
+            max_payload_array_length: model.max_payload_array_length.map(|x| x as u16),
 
             created_at: model.created_at.into(),
             updated_at: model.updated_at.into(),
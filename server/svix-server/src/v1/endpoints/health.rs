@@ -61,12 +61,18 @@ impl<O, E> From<Result<O, E>> for HealthStatus {
     }
 }
 
+/// The outcome of [`crate::core::security::check_signing_key`] on startup, reported by the
+/// readiness endpoint
+#[derive(Clone, Debug)]
+pub struct SigningKeyCheck(pub crate::error::Result<()>);
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct HealthReport {
     database: HealthStatus,
 
     queue: HealthStatus,
     cache: HealthStatus,
+    signing_key: HealthStatus,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
@@ -77,6 +83,7 @@ async fn health(
     Extension(ref db): Extension<DatabaseConnection>,
     Extension(queue_tx): Extension<TaskQueueProducer>,
     Extension(cache): Extension<Cache>,
+    Extension(SigningKeyCheck(signing_key)): Extension<SigningKeyCheck>,
 ) -> (StatusCode, Json<HealthReport>) {
     // SELECT 1 FROM any table
     let database: HealthStatus = db
@@ -101,7 +108,10 @@ async fn health(
         .await
         .into();
 
-    let status = if database.is_ok() && queue.is_ok() && cache.is_ok() {
+    // Checked once on startup, as the key doesn't change while running
+    let signing_key: HealthStatus = signing_key.into();
+
+    let status = if database.is_ok() && queue.is_ok() && cache.is_ok() && signing_key.is_ok() {
         StatusCode::OK
     } else {
         StatusCode::INTERNAL_SERVER_ERROR
@@ -113,6 +123,7 @@ async fn health(
             database,
             queue,
             cache,
+            signing_key,
         }),
     )
 }
@@ -3,15 +3,21 @@
 
 use crate::{
     cache::Cache,
+    cfg::Configuration,
     core::{
         message_app::CreateMessageApp,
+        operational_webhooks::{
+            MessageNoMatchingEndpointsEvent, OperationalWebhook, OperationalWebhookSender,
+        },
         security::{AuthenticatedApplication, AuthenticatedOrganizationWithApplication},
         types::{
             ApplicationIdOrUid, EventChannel, EventChannelSet, EventTypeName, EventTypeNameSet,
             MessageAttemptTriggerType, MessageId, MessageIdOrUid, MessageUid,
+            RetryScheduleOverride,
         },
     },
-    error::{Error, HttpError, Result},
+    db::models::eventtype,
+    error::{Error, HttpError, Result, ValidationErrorItem},
     queue::{MessageTaskBatch, TaskQueueProducer},
     v1::utils::{
         apply_pagination, iterator_from_before_or_after, ListResponse, MessageListFetchOptions,
@@ -49,6 +55,63 @@ pub fn validate_channels_msg(
     }
 }
 
+/// Returns the nesting depth of objects and arrays in a payload, and the length of the longest
+/// array it contains
+fn payload_structure(value: &serde_json::Value) -> (usize, usize) {
+    let (children, array_len): (Box<dyn Iterator<Item = &serde_json::Value>>, usize) = match value {
+        serde_json::Value::Object(map) => (Box::new(map.values()), 0),
+        serde_json::Value::Array(items) => (Box::new(items.iter()), items.len()),
+        _ => return (0, 0),
+    };
+
+    children
+        .map(payload_structure)
+        .fold((1, array_len), |(depth, len), (d, l)| {
+            (depth.max(d + 1), len.max(l))
+        })
+}
+
+/// Enforces the structural payload limits configured on an event type
+fn validate_payload_structure(
+    payload: &serde_json::Value,
+    event_type: &eventtype::Model,
+) -> Result<()> {
+    let (depth, longest_array) = payload_structure(payload);
+    let mut errors = Vec::new();
+
+    if let Some(max) = event_type.max_payload_depth {
+        if depth > max as usize {
+            errors.push(ValidationErrorItem {
+                loc: vec!["body".to_owned(), "payload".to_owned()],
+                msg: format!(
+                    "Payload depth of {} exceeds the maximum of {} for event type `{}`",
+                    depth, max, event_type.name
+                ),
+                ty: "value_error".to_owned(),
+            });
+        }
+    }
+
+    if let Some(max) = event_type.max_payload_array_length {
+        if longest_array > max as usize {
+            errors.push(ValidationErrorItem {
+                loc: vec!["body".to_owned(), "payload".to_owned()],
+                msg: format!(
+                    "Payload array length of {} exceeds the maximum of {} for event type `{}`",
+                    longest_array, max, event_type.name
+                ),
+                ty: "value_error".to_owned(),
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(HttpError::unprocessable_entity(errors).into())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Validate, ModelIn)]
 #[serde(rename_all = "camelCase")]
 pub struct MessageIn {
@@ -66,6 +129,10 @@ pub struct MessageIn {
     #[validate(range(min = 5, max = 90))]
     #[serde(default = "default_90")]
     pub payload_retention_period: i64,
+    /// Optional retry policy for this message, superseding the default retry schedule
+    #[validate]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_schedule: Option<RetryScheduleOverride>,
 }
 
 // FIXME: This can and should be a derive macro
@@ -80,6 +147,7 @@ impl ModelIn for MessageIn {
         model.event_type = Set(self.event_type);
         model.expiration = Set(expiration.with_timezone(&Utc).into());
         model.channels = Set(self.channels);
+        model.retry_schedule = Set(self.retry_schedule);
     }
 }
 
@@ -94,6 +162,9 @@ pub struct MessageOut {
     pub id: MessageId,
     #[serde(rename = "timestamp")]
     pub created_at: DateTime<Utc>,
+    /// Set when none of the application's endpoints matched the message when it was created, so
+    /// it wasn't sent anywhere
+    pub no_matching_endpoints: bool,
 }
 
 impl MessageOut {
@@ -118,6 +189,7 @@ impl From<message::Model> for MessageOut {
             channels: model.channels,
             id: model.id,
             created_at: model.created_at.into(),
+            no_matching_endpoints: model.no_matching_endpoints,
         }
     }
 }
@@ -197,6 +269,8 @@ async fn create_message(
     Extension(ref db): Extension<DatabaseConnection>,
     Extension(queue_tx): Extension<TaskQueueProducer>,
     Extension(cache): Extension<Cache>,
+    Extension(cfg): Extension<Configuration>,
+    Extension(op_webhooks): Extension<OperationalWebhookSender>,
     ValidatedQuery(CreateMessageQueryParams { with_content }): ValidatedQuery<
         CreateMessageQueryParams,
     >,
@@ -215,28 +289,57 @@ async fn create_message(
     // Should never happen since you're giving it an existing Application, but just in case
     .ok_or_else(|| Error::Generic(format!("Application doesn't exist: {}", app.id)))?;
 
+    if let Some(event_type) =
+        eventtype::Entity::secure_find_by_name(permissions.org_id.clone(), data.event_type.clone())
+            .filter(eventtype::Column::Deleted.eq(false))
+            .one(db)
+            .await?
+    {
+        validate_payload_structure(&data.payload, &event_type)?;
+    }
+
+    let trigger_type = MessageAttemptTriggerType::Scheduled;
+    let no_matching_endpoints = !create_message_app
+        .filtered_endpoints_for(trigger_type, &data.event_type, data.channels.as_ref())
+        .iter()
+        .any(|endpoint| !endpoint.draining);
+
     let msg = message::ActiveModel {
         app_id: Set(app.id.clone()),
         org_id: Set(permissions.org_id),
+        no_matching_endpoints: Set(no_matching_endpoints),
         ..data.into()
     };
     let msg = msg.insert(db).await?;
 
-    let trigger_type = MessageAttemptTriggerType::Scheduled;
-    if !create_message_app
-        .filtered_endpoints(trigger_type, &msg)
-        .is_empty()
-    {
+    if !no_matching_endpoints {
         queue_tx
             .send(
-                MessageTaskBatch::new_task(
-                    msg.id.clone(),
-                    app.id.clone(),
-                    MessageAttemptTriggerType::Scheduled,
-                ),
+                MessageTaskBatch::new_task(msg.id.clone(), app.id.clone(), trigger_type),
                 None,
             )
             .await?;
+    } else if cfg.message_no_matching_endpoints_alerts {
+        // The message is already stored, so failing to send the alert doesn't fail the request
+        if let Err(e) = op_webhooks
+            .send_operational_webhook(
+                &msg.org_id,
+                OperationalWebhook::MessageNoMatchingEndpoints(MessageNoMatchingEndpointsEvent {
+                    app_id: &msg.app_id,
+                    app_uid: app.uid.as_ref(),
+                    msg_id: &msg.id,
+                    msg_event_id: msg.uid.as_ref(),
+                    event_type: &msg.event_type,
+                }),
+            )
+            .await
+        {
+            tracing::error!(
+                "Error sending the no matching endpoints alert for message {}: {}",
+                msg.id,
+                e
+            );
+        }
     }
 
     let msg_out = if with_content {
@@ -257,14 +360,12 @@ async fn get_message(
     Extension(ref db): Extension<DatabaseConnection>,
     Path((_app_id, msg_id)): Path<(ApplicationIdOrUid, MessageIdOrUid)>,
     ValidatedQuery(GetMessageQueryParams { with_content }): ValidatedQuery<GetMessageQueryParams>,
-    AuthenticatedApplication {
-        permissions: _,
-        app,
-    }: AuthenticatedApplication,
+    AuthenticatedApplication { permissions, app }: AuthenticatedApplication,
 ) -> Result<Json<MessageOut>> {
     let msg = message::Entity::secure_find_by_id_or_uid(app.id, msg_id)
         .one(db)
         .await?
+        .filter(|msg| permissions.allows_message(&msg.id))
         .ok_or_else(|| HttpError::not_found(None, None))?;
     let msg_out = if with_content {
         msg.into()
@@ -286,8 +387,8 @@ pub fn router() -> Router {
 #[cfg(test)]
 mod tests {
     use super::{
-        default_true, CreateMessageQueryParams, GetMessageQueryParams, ListMessagesQueryParams,
-        MessageIn,
+        default_true, payload_structure, CreateMessageQueryParams, GetMessageQueryParams,
+        ListMessagesQueryParams, MessageIn,
     };
     use serde_json::json;
     use validator::Validate;
@@ -348,6 +449,18 @@ mod tests {
         valid.validate().unwrap();
     }
 
+    #[test]
+    fn test_payload_structure() {
+        assert_eq!(payload_structure(&json!("value")), (0, 0));
+        assert_eq!(payload_structure(&json!({})), (1, 0));
+        assert_eq!(payload_structure(&json!({"a": {"b": 1}, "c": 2})), (2, 0));
+        assert_eq!(payload_structure(&json!({"a": [1, 2, 3]})), (2, 3));
+        assert_eq!(
+            payload_structure(&json!([[1, 2], {"a": [{"b": [1, 2, 3, 4]}]}])),
+            (5, 4)
+        );
+    }
+
     #[test]
     fn test_default_true() {
         assert!(default_true());
@@ -6,11 +6,16 @@ use crate::core::types::{ApplicationUid, MessageUid, OrganizationId};
 use crate::core::{
     cache::Cache,
     message_app::{CreateMessageApp, CreateMessageEndpoint},
-    operational_webhooks::{MessageAttemptEvent, OperationalWebhook, OperationalWebhookSender},
+    operational_webhooks::{
+        EndpointTlsFingerprintChangedEvent, MessageAttemptEvent, OperationalWebhook,
+        OperationalWebhookSender,
+    },
+    tls_pinning::pinned_tls_config,
     types::{
-        BaseId, EndpointHeaders, EndpointSecret, MessageAttemptId, MessageAttemptTriggerType,
-        MessageId, MessageStatus,
+        AttemptFailureKind, BaseId, EndpointHeaders, EndpointSecret, MessageAttemptId,
+        MessageAttemptTriggerType, MessageId, MessageStatus, RetryScheduleOverride, TlsFingerprint,
     },
+    webhook_signature::{sign_msg, SignatureHeaderNames},
 };
 use crate::db::models::{message, messageattempt, messagedestination};
 use crate::error::{Error, Result};
@@ -21,7 +26,7 @@ use chrono::Utc;
 use futures::future;
 use rand::Rng;
 use reqwest::header::{HeaderMap, HeaderName};
-use sea_orm::{entity::prelude::*, ActiveValue::Set, DatabaseConnection, EntityTrait};
+use sea_orm::{entity::prelude::*, ActiveValue::Set, DatabaseConnection, EntityTrait, QueryOrder};
 use tokio::time::{sleep, Duration};
 
 use std::{iter, str::FromStr};
@@ -34,28 +39,6 @@ const USER_AGENT: &str = concat!("Svix-Webhooks/", env!("CARGO_PKG_VERSION"));
 /// Send the MessageAttemptFailingEvent after exceeding this number of failed attempts
 const OP_WEBHOOKS_SEND_FAILING_EVENT_AFTER: usize = 4;
 
-fn to_sign(timestamp: i64, body: &str, msg_id: &MessageId) -> String {
-    format!("{}.{}.{}", msg_id, timestamp, body)
-}
-
-/// Sign a message
-fn sign_msg(
-    timestamp: i64,
-    body: &str,
-    msg_id: &MessageId,
-    endpoint_signing_keys: &[&EndpointSecret],
-) -> String {
-    let to_sign = to_sign(timestamp, body, msg_id);
-    let signatures = endpoint_signing_keys
-        .iter()
-        .map(|x| hmac_sha256::HMAC::mac(to_sign.as_bytes(), &x.0[..]));
-
-    signatures
-        .map(|x| format!("v1,{}", base64::encode(x)))
-        .collect::<Vec<String>>()
-        .join(" ")
-}
-
 /// Generates a set of headers for any one webhook event
 fn generate_msg_headers(
     timestamp: i64,
@@ -74,15 +57,10 @@ fn generate_msg_headers(
     let signatures_str = signatures
         .parse()
         .expect("Error parsing message signatures");
-    if whitelabel_headers {
-        headers.insert("webhook-id", id);
-        headers.insert("webhook-timestamp", timestamp);
-        headers.insert("webhook-signature", signatures_str);
-    } else {
-        headers.insert("svix-id", id);
-        headers.insert("svix-timestamp", timestamp);
-        headers.insert("svix-signature", signatures_str);
-    }
+    let header_names = SignatureHeaderNames::new(whitelabel_headers);
+    headers.insert(header_names.id, id);
+    headers.insert(header_names.timestamp, timestamp);
+    headers.insert(header_names.signature, signatures_str);
 
     if let Some(configured_headers) = configured_headers {
         for (k, v) in &configured_headers.0 {
@@ -136,6 +114,7 @@ async fn dispatch(
         msg_uid,
     }: DispatchExtraIds<'_>,
     payload: &Json,
+    retry_schedule_override: Option<&RetryScheduleOverride>,
     endp: CreateMessageEndpoint,
 ) -> Result<()> {
     tracing::trace!("Dispatch: {} {}", &msg_task.msg_id, &endp.id);
@@ -167,8 +146,13 @@ async fn dispatch(
 
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
-        .build()
-        .expect("Invalid reqwest Client configuration");
+        .tls_info(true);
+    let client = match &endp.pinned_tls_fingerprint {
+        Some(fingerprint) => client.use_preconfigured_tls(pinned_tls_config(fingerprint.clone())),
+        None => client,
+    }
+    .build()
+    .expect("Invalid reqwest Client configuration");
     let res = client
         .post(&endp.url)
         .headers(headers)
@@ -177,6 +161,13 @@ async fn dispatch(
         .send()
         .await;
 
+    let tls_fingerprint = res
+        .as_ref()
+        .ok()
+        .and_then(|res| res.extensions().get::<reqwest::tls::TlsInfo>())
+        .and_then(|info| info.peer_certificate())
+        .map(TlsFingerprint::from_certificate);
+
     let msg_dest = messagedestination::Entity::secure_find_by_msg(msg_task.msg_id.clone())
         .filter(messagedestination::Column::EndpId.eq(endp.id.clone()))
         .one(db)
@@ -200,6 +191,22 @@ async fn dispatch(
         return Ok(());
     }
 
+    // Pinned endpoints reject other certificates during the handshake, so they never change
+    let previous_tls_fingerprint = match &tls_fingerprint {
+        Some(fingerprint)
+            if cfg.endpoint_tls_fingerprint_alerts && endp.pinned_tls_fingerprint.is_none() =>
+        {
+            messageattempt::Entity::secure_find_by_endpoint(endp.id.clone())
+                .filter(messageattempt::Column::TlsFingerprint.is_not_null())
+                .order_by_desc(messageattempt::Column::Id)
+                .one(db)
+                .await?
+                .and_then(|attempt| attempt.tls_fingerprint)
+                .filter(|previous| previous != fingerprint)
+        }
+        _ => None,
+    };
+
     let attempt = messageattempt::ActiveModel {
         // Set both ID and created_at to the same timestamp
         id: Set(MessageAttemptId::new(now.into(), None)),
@@ -210,6 +217,8 @@ async fn dispatch(
         url: Set(endp.url.clone()),
         ended_at: Set(Some(Utc::now().into())),
         trigger_type: Set(msg_task.trigger_type),
+        custom_retry_schedule: Set(retry_schedule_override.is_some()),
+        tls_fingerprint: Set(tls_fingerprint),
         ..Default::default()
     };
     let attempt = match res {
@@ -244,17 +253,48 @@ async fn dispatch(
                 response_status_code: Set(0),
                 response: Set("".to_owned()),
                 status: Set(MessageStatus::Fail),
+                failure_kind: Set(Some(failure_kind(&err))),
 
                 ..attempt
             };
             Err((attempt, err))
         }
     };
+    let (attempt, err) = match attempt {
+        Ok(attempt) => (attempt.insert(db).await?, None),
+        Err((attempt, err)) => (attempt.insert(db).await?, Some(err)),
+    };
 
-    match attempt {
-        Ok(attempt) => {
-            let _attempt = attempt.insert(db).await?;
+    // The alert is only sent once the attempt is recorded, and failing to send it doesn't fail
+    // the delivery
+    if let (Some(previous), Some(fingerprint)) =
+        (&previous_tls_fingerprint, &attempt.tls_fingerprint)
+    {
+        if let Err(e) = op_webhook_sender
+            .send_operational_webhook(
+                org_id,
+                OperationalWebhook::EndpointTlsFingerprintChanged(
+                    EndpointTlsFingerprintChangedEvent {
+                        app_id: &msg_task.app_id,
+                        app_uid,
+                        endpoint_id: &endp.id,
+                        previous_fingerprint: previous,
+                        fingerprint,
+                    },
+                ),
+            )
+            .await
+        {
+            tracing::error!(
+                "Error sending the TLS fingerprint change of endpoint {}: {}",
+                endp.id,
+                e
+            );
+        }
+    }
 
+    match err {
+        None => {
             let msg_dest = messagedestination::ActiveModel {
                 status: Set(MessageStatus::Success),
                 next_attempt: Set(None),
@@ -263,13 +303,16 @@ async fn dispatch(
             let msg_dest = msg_dest.update(db).await?;
             tracing::trace!("Worker success: {} {}", &msg_dest.id, &endp.id,);
         }
-        Err((attempt, err)) => {
-            let attempt = attempt.insert(db).await?;
+        Some(err) => {
+            // A retry schedule given with the message supersedes the configured one
+            let retry_schedule = retry_schedule_override
+                .map(RetryScheduleOverride::durations)
+                .unwrap_or_else(|| cfg.retry_schedule.clone());
 
             let attempt_count = msg_task.attempt_count as usize;
             if msg_task.trigger_type == MessageAttemptTriggerType::Manual {
                 tracing::debug!("Manual retry failed");
-            } else if attempt_count < cfg.retry_schedule.len() {
+            } else if attempt_count < retry_schedule.len() {
                 tracing::debug!(
                     "Worker failure retrying for attempt {}: {} {} {}",
                     attempt_count,
@@ -278,7 +321,7 @@ async fn dispatch(
                     &endp.id
                 );
 
-                let duration = cfg.retry_schedule[attempt_count];
+                let duration = retry_schedule[attempt_count];
 
                 // Apply jitter with a maximum variation of JITTER_DELTA
                 let duration = rand::thread_rng().gen_range(
@@ -357,6 +400,33 @@ async fn dispatch(
     Ok(())
 }
 
+/// Classifies why a request failed without a response. TLS failures, such as certificates not
+/// matching the pinned fingerprint, surface as connection errors, with the [`rustls::Error`] at the
+/// bottom of the error's sources.
+fn failure_kind(err: &reqwest::Error) -> AttemptFailureKind {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        // `io::Error`s skip the error they wrap when asked for their source
+        let tls = err.is::<rustls::Error>()
+            || matches!(
+                err.downcast_ref::<std::io::Error>().and_then(|err| err.get_ref()),
+                Some(inner) if inner.is::<rustls::Error>()
+            );
+        if tls {
+            return AttemptFailureKind::Tls;
+        }
+        source = std::error::Error::source(err);
+    }
+
+    if err.is_timeout() {
+        AttemptFailureKind::Timeout
+    } else if err.is_connect() {
+        AttemptFailureKind::Connection
+    } else {
+        AttemptFailureKind::Other
+    }
+}
+
 fn bytes_to_string(bytes: bytes::Bytes) -> String {
     match std::str::from_utf8(&bytes) {
         Ok(v) => v.to_owned(),
@@ -413,7 +483,8 @@ async fn process_task(worker_context: WorkerContext<'_>, queue_task: QueueTask)
         .filter(|endpoint| match &queue_task {
             QueueTask::HealthCheck => unreachable!(),
             QueueTask::MessageV1(task) => task.endpoint_id == endpoint.id,
-            QueueTask::MessageBatch(_) => true,
+            // Draining endpoints don't accept new messages
+            QueueTask::MessageBatch(_) => !endpoint.draining,
         })
         .cloned()
         .collect();
@@ -466,6 +537,7 @@ async fn process_task(worker_context: WorkerContext<'_>, queue_task: QueueTask)
                     msg_uid: msg_uid.as_ref(),
                 },
                 payload,
+                msg.retry_schedule.as_ref(),
                 endpoint,
             )
         })
@@ -553,7 +625,7 @@ mod tests {
     /// Utility function that returns the default set of headers before configurable header are
     /// accounted for
     fn mock_headers() -> (HeaderMap, MessageId) {
-        let id = MessageId::new(None, None);
+        let id = MessageId::generate();
 
         let signatures = sign_msg(TIMESTAMP, BODY, &id, ENDPOINT_SIGNING_KEYS);
 
@@ -4,15 +4,20 @@
 use reqwest::StatusCode;
 
 use svix_server::{
-    cfg::CacheType, core::types::ApplicationUid, v1::endpoints::application::ApplicationIn,
-    v1::endpoints::application::ApplicationOut,
+    cfg::CacheType,
+    core::types::ApplicationUid,
+    v1::endpoints::application::{ApplicationIn, ApplicationOut, VerificationSnippetOut},
+    v1::endpoints::endpoint::EndpointSecretOut,
 };
 
 mod utils;
 
 use utils::{
-    common_calls::{application_in, common_test_list},
-    start_svix_server, IgnoredResponse,
+    common_calls::{
+        application_in, common_test_list, create_test_app, create_test_endpoint,
+        create_test_message,
+    },
+    start_svix_server, IgnoredResponse, TestReceiver,
 };
 
 #[tokio::test]
@@ -445,3 +450,61 @@ async fn test_idempotency() {
         _ => assert_eq!(app1, app2),
     };
 }
+
+#[tokio::test]
+async fn test_verification_snippet_matches_signature() {
+    let (client, _jh) = start_svix_server();
+
+    let app_id = create_test_app(&client, "app1").await.unwrap().id;
+
+    let mut receiver = TestReceiver::start(StatusCode::OK);
+    let endp = create_test_endpoint(&client, &app_id, &receiver.endpoint)
+        .await
+        .unwrap();
+    let secret: EndpointSecretOut = client
+        .get(
+            &format!("api/v1/app/{}/endpoint/{}/secret/", app_id, endp.id),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+
+    let snippet: VerificationSnippetOut = client
+        .get(
+            &format!("api/v1/app/{}/verification-snippet/", app_id),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    assert_eq!(snippet.algorithm, "HMAC-SHA256");
+    assert!(!snippet.snippet.contains(&base64::encode(&secret.key.0)));
+
+    create_test_message(&client, &app_id, serde_json::json!({"test": "data1"}))
+        .await
+        .unwrap();
+
+    let headers = receiver.header_recv.recv().await.unwrap();
+    let body = receiver.data_recv.recv().await.unwrap().to_string();
+
+    // Following the described scheme results in the signature the server sent
+    let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_owned();
+    let signed_content = snippet
+        .signed_content
+        .replace("{id}", &header(&snippet.id_header))
+        .replace("{timestamp}", &header(&snippet.timestamp_header))
+        .replace("{body}", &body);
+    let expected = format!(
+        "{},{}",
+        snippet.signature_version,
+        base64::encode(hmac_sha256::HMAC::mac(
+            signed_content.as_bytes(),
+            &secret.key.0[..]
+        ))
+    );
+
+    assert!(header(&snippet.signature_header)
+        .split(' ')
+        .any(|x| x == expected));
+
+    receiver.jh.abort();
+}
@@ -4,7 +4,7 @@
 use reqwest::StatusCode;
 
 use svix_server::{
-    core::types::{EndpointUid, MessageStatus},
+    core::types::{AttemptFailureKind, EndpointUid, MessageStatus, TlsFingerprint},
     v1::{
         endpoints::{
             attempt::{AttemptedMessageOut, MessageAttemptOut},
@@ -19,13 +19,59 @@ mod utils;
 use utils::{
     common_calls::{
         create_test_app, create_test_endpoint, create_test_message, endpoint_in,
-        get_msg_attempt_list_and_assert_count,
+        get_msg_attempt_list_and_assert_count, post_endpoint,
     },
     get_default_test_config, run_with_retries, start_svix_server, start_svix_server_with_cfg,
     TestReceiver,
 };
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
+
+/// A self-signed certificate for `localhost`, and its key
+const TLS_CERT: &str = "\
+MIIBlDCCATugAwIBAgIUXeJ8EDnXKDwvRxhMWcjYx2zowpswCgYIKoZIzj0EAwIw\
+FDESMBAGA1UEAwwJbG9jYWxob3N0MCAXDTI2MTAxNTAzMjUzNVoYDzIxMjYwOTIx\
+MDMyNTM1WjAUMRIwEAYDVQQDDAlsb2NhbGhvc3QwWTATBgcqhkjOPQIBBggqhkjO\
+PQMBBwNCAATNqwFumiJMftLl2TsSOSLeX/IpW8ddPBhmvhlkeJ/p2IUzxyOXlY73\
+ikEqLbTd8WXgBquqeOWFYbau//4bw2+Qo2kwZzAdBgNVHQ4EFgQU/6wmUHGQauKZ\
+oIdVggt6ESuG+08wHwYDVR0jBBgwFoAU/6wmUHGQauKZoIdVggt6ESuG+08wDwYD\
+VR0TAQH/BAUwAwEB/zAUBgNVHREEDTALgglsb2NhbGhvc3QwCgYIKoZIzj0EAwID\
+RwAwRAIgCifph+fLRnCaT5YghxDJCtqY9TT8p9NRK/pwiGeYiBMCIEG7sSQa7a1q\
+V44POYczUSIAvOnR9s9QskDfciZSvXdi";
+const TLS_KEY: &str = "\
+MIGHAgEAMBMGByqGSM49AgEGCCqGSM49AwEHBG0wawIBAQQgZ+q3M+1Z28PtSKkm\
+0u8AUtQPdpwhDYT703yoE+GP5CehRANCAATNqwFumiJMftLl2TsSOSLeX/IpW8dd\
+PBhmvhlkeJ/p2IUzxyOXlY73ikEqLbTd8WXgBquqeOWFYbau//4bw2+Q";
+
+/// Starts an https server presenting [`TLS_CERT`], returning its URL. Requests never get past the
+/// handshake, as the certificate isn't trusted by the worker.
+fn start_tls_server() -> String {
+    let config = Arc::new(
+        rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(base64::decode(TLS_CERT).unwrap())],
+                rustls::PrivateKey(base64::decode(TLS_KEY).unwrap()),
+            )
+            .unwrap(),
+    );
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "https://localhost:{}/",
+        listener.local_addr().unwrap().port()
+    );
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let conn = rustls::ServerConnection::new(config.clone()).unwrap();
+            let mut stream = rustls::StreamOwned::new(conn, stream);
+            let _ = std::io::Read::read(&mut stream, &mut [0; 1024]);
+        }
+    });
+
+    url
+}
 
 #[tokio::test]
 async fn test_list_attempted_messages() {
@@ -172,6 +218,9 @@ async fn test_list_attempts_by_endpoint() {
         .unwrap();
 
     for list in [list_1, list_2] {
+        // Plain HTTP deliveries have no certificate to fingerprint
+        assert!(list.data.iter().all(|amo| amo.tls_fingerprint.is_none()));
+
         let message_ids: Vec<_> = list.data.into_iter().map(|amo| amo.msg_id).collect();
         assert!(message_ids.contains(&msg_1.id));
         assert!(message_ids.contains(&msg_2.id));
@@ -267,6 +316,41 @@ async fn test_message_attempts() {
     }
 }
 
+#[tokio::test]
+async fn test_pinned_tls_fingerprint_mismatch() {
+    let mut cfg = get_default_test_config();
+    cfg.retry_schedule = vec![];
+
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+    let app_id = create_test_app(&client, "app").await.unwrap().id;
+
+    let endp_id = post_endpoint(
+        &client,
+        &app_id,
+        EndpointIn {
+            pinned_tls_fingerprint: Some(TlsFingerprint::from_certificate(b"another certificate")),
+            ..endpoint_in(&start_tls_server())
+        },
+    )
+    .await
+    .unwrap()
+    .id;
+
+    let msg = create_test_message(&client, &app_id, serde_json::json!({"test": "data"}))
+        .await
+        .unwrap();
+
+    let list = get_msg_attempt_list_and_assert_count(&client, &app_id, &msg.id, 1)
+        .await
+        .unwrap();
+    let attempt = &list.data[0];
+    assert_eq!(attempt.endpoint_id, endp_id);
+    assert_eq!(attempt.status, MessageStatus::Fail);
+    assert_eq!(attempt.response_status_code, 0);
+    assert_eq!(attempt.failure_kind, Some(AttemptFailureKind::Tls));
+    assert_eq!(attempt.tls_fingerprint, None);
+}
+
 #[tokio::test]
 async fn test_message_attempts_empty_retry_schedule() {
     let mut cfg = get_default_test_config();
@@ -2,15 +2,41 @@
 //! that the tokens returned by the endpoint have restricted functionality and that the response
 //! from the endpoint is valid in the process.
 
+use std::sync::Arc;
+
+use axum::{extract::Extension, response::IntoResponse, routing::get, Json, Router};
 use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, EntityTrait, Set};
+use tower::ServiceExt;
 
 use svix_server::{
-    core::types::ApplicationId,
-    v1::endpoints::{application::ApplicationOut, auth::DashboardAccessOut},
+    core::{
+        security::{
+            generate_app_token, generate_app_token_for_env, generate_app_token_for_uid,
+            generate_management_token, generate_message_read_token, generate_multi_org_token,
+            generate_org_token, generate_scoped_app_token, AuthenticatedOrganization,
+            AuthenticatedOrganizationOrApplication,
+            AuthenticatedOrganizationOrManagementWithApplication,
+            AuthenticatedOrganizationWithApplication, Permissions,
+        },
+        types::{ApplicationId, ApplicationUid, BaseId, OrganizationId},
+    },
+    db::models::application,
+    v1::endpoints::{
+        application::{ApplicationIn, ApplicationOut},
+        auth::DashboardAccessOut,
+        message::MessageOut,
+    },
 };
 
 mod utils;
-use utils::{common_calls::application_in, start_svix_server, IgnoredResponse, TestClient};
+use utils::{
+    common_calls::{
+        application_in, create_test_endpoint, create_test_message, endpoint_in, message_in,
+    },
+    get_default_test_config, start_svix_server, start_svix_server_with_cfg, IgnoredResponse,
+    TestClient,
+};
 
 /// Accesses the dashboard-access endpoint and returns a new [`TestClient`] with an auth header set
 /// to the returned token.
@@ -88,8 +114,585 @@ async fn test_restricted_application_access() {
         .get(&format!("api/v1/app/{}", app_id_2), StatusCode::NOT_FOUND)
         .await
         .unwrap();
+    let _: IgnoredResponse = client
+        .get(
+            &format!("api/v1/app/{}/endpoint/", app_id_2),
+            StatusCode::NOT_FOUND,
+        )
+        .await
+        .unwrap();
     let _: ApplicationOut = client
         .get(&format!("api/v1/app/{}", app_id), StatusCode::OK)
         .await
         .unwrap();
 }
+
+#[tokio::test]
+/// Tokens with an `orgs` claim may access the applications of the listed organizations, but not
+/// those of any other organization.
+async fn test_multi_org_token_access() {
+    let cfg = get_default_test_config();
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+
+    let org_client = |org_id: &OrganizationId| {
+        let mut client = client.clone();
+        client.set_auth_header(generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap());
+        client
+    };
+
+    let permitted_org = OrganizationId::generate();
+    let other_org = OrganizationId::generate();
+
+    let permitted_app: ApplicationOut = org_client(&permitted_org)
+        .post(
+            "api/v1/app/",
+            application_in("TEST_APP_NAME"),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+    let other_app: ApplicationOut = org_client(&other_org)
+        .post(
+            "api/v1/app/",
+            application_in("TEST_APP_NAME"),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+
+    let mut client = client.clone();
+    client.set_auth_header(
+        generate_multi_org_token(
+            &cfg.jwt_secret,
+            OrganizationId::generate(),
+            vec![permitted_org.clone()],
+        )
+        .unwrap(),
+    );
+
+    let app: ApplicationOut = client
+        .get(&format!("api/v1/app/{}/", permitted_app.id), StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(app, permitted_app);
+
+    let _: IgnoredResponse = client
+        .get(
+            &format!("api/v1/app/{}/", other_app.id),
+            StatusCode::NOT_FOUND,
+        )
+        .await
+        .unwrap();
+
+    // Listing other organizations doesn't take away access to the token's own one
+    let third_org = OrganizationId::generate();
+    let third_app: ApplicationOut = org_client(&third_org)
+        .post(
+            "api/v1/app/",
+            application_in("TEST_APP_NAME"),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+
+    let mut client = client.clone();
+    client.set_auth_header(
+        generate_multi_org_token(&cfg.jwt_secret, other_org, vec![permitted_org]).unwrap(),
+    );
+
+    for app in [&other_app, &permitted_app] {
+        let _: ApplicationOut = client
+            .get(&format!("api/v1/app/{}/", app.id), StatusCode::OK)
+            .await
+            .unwrap();
+    }
+
+    let _: IgnoredResponse = client
+        .get(
+            &format!("api/v1/app/{}/", third_app.id),
+            StatusCode::NOT_FOUND,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+/// Application tokens can be minted for an application's UID, but only within its organization.
+async fn test_app_token_for_uid() {
+    let cfg = get_default_test_config();
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+    let pool = svix_server::db::init_db(&Arc::new(cfg.clone())).await;
+
+    let org_id = OrganizationId::generate();
+    let mut org_client = client.clone();
+    org_client.set_auth_header(generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap());
+
+    let uid = ApplicationUid("test-app-uid".to_owned());
+    let app: ApplicationOut = org_client
+        .post(
+            "api/v1/app/",
+            ApplicationIn {
+                name: "TEST_APP_NAME".to_owned(),
+                uid: Some(uid.clone()),
+                ..Default::default()
+            },
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+
+    let token = generate_app_token_for_uid(&cfg.jwt_secret, &pool, org_id, uid.clone().into())
+        .await
+        .unwrap();
+    let mut app_client = client.clone();
+    app_client.set_auth_header(token);
+    let fetched: ApplicationOut = app_client
+        .get(&format!("api/v1/app/{}/", app.id), StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(fetched, app);
+
+    // The UID doesn't exist in any other organization
+    let err = generate_app_token_for_uid(
+        &cfg.jwt_secret,
+        &pool,
+        OrganizationId::generate(),
+        uid.into(),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.into_response().status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+/// Both organization tokens and application tokens resolve the application in the path, but only
+/// the latter are scoped to it.
+async fn test_organization_or_application_extractor() {
+    async fn handler(
+        AuthenticatedOrganizationOrApplication {
+            app,
+            scoped_to_single_app,
+            ..
+        }: AuthenticatedOrganizationOrApplication,
+    ) -> Json<(ApplicationId, bool)> {
+        Json((app.id, scoped_to_single_app))
+    }
+
+    let cfg = get_default_test_config();
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+    let pool = svix_server::db::init_db(&Arc::new(cfg.clone())).await;
+
+    let org_id = OrganizationId::generate();
+    let org_token = generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap();
+    let mut org_client = client.clone();
+    org_client.set_auth_header(org_token.clone());
+    let app: ApplicationOut = org_client
+        .post(
+            "api/v1/app/",
+            application_in("TEST_APP_NAME"),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+    let app_token = generate_app_token(&cfg.jwt_secret, org_id, app.id.clone()).unwrap();
+
+    let router = Router::new()
+        .route("/app/:app_id/", get(handler))
+        .layer(Extension(pool))
+        .layer(Extension(Arc::new(cfg)));
+
+    for (token, scoped) in [(org_token, false), (app_token, true)] {
+        let resp = router
+            .clone()
+            .oneshot(
+                http::Request::builder()
+                    .uri(format!("/app/{}/", app.id))
+                    .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+                    .body(hyper::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let (app_id, scoped_to_single_app): (ApplicationId, bool) =
+            serde_json::from_slice(&body).unwrap();
+        assert_eq!(app_id, app.id);
+        assert_eq!(scoped_to_single_app, scoped);
+    }
+}
+
+#[tokio::test]
+/// Management tokens can access the applications of any organization, while organization tokens
+/// are still limited to their own.
+async fn test_organization_or_management_with_application_extractor() {
+    async fn handler(
+        AuthenticatedOrganizationOrManagementWithApplication { permissions, app }: AuthenticatedOrganizationOrManagementWithApplication,
+    ) -> Json<(OrganizationId, ApplicationId)> {
+        Json((permissions.org_id, app.id))
+    }
+
+    let cfg = get_default_test_config();
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+    let pool = svix_server::db::init_db(&Arc::new(cfg.clone())).await;
+
+    let org_id = OrganizationId::generate();
+    let org_token = generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap();
+    let mut org_client = client.clone();
+    org_client.set_auth_header(org_token.clone());
+    let app: ApplicationOut = org_client
+        .post(
+            "api/v1/app/",
+            application_in("TEST_APP_NAME"),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+
+    let management_token =
+        generate_management_token(&cfg.jwt_secret, cfg.management_org_id.clone()).unwrap();
+    let other_org_token = generate_org_token(&cfg.jwt_secret, OrganizationId::generate()).unwrap();
+
+    let router = Router::new()
+        .route("/app/:app_id/", get(handler))
+        .layer(Extension(pool))
+        .layer(Extension(Arc::new(cfg)));
+    let request = |token: &str| {
+        http::Request::builder()
+            .uri(format!("/app/{}/", app.id))
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(hyper::Body::empty())
+            .unwrap()
+    };
+
+    // The owning organization and the management organization find the application, with the
+    // permissions scoped to the owning organization
+    for token in [&org_token, &management_token] {
+        let resp = router.clone().oneshot(request(token)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let (permitted_org_id, app_id): (OrganizationId, ApplicationId) =
+            serde_json::from_slice(&body).unwrap();
+        assert_eq!(permitted_org_id, org_id);
+        assert_eq!(app_id, app.id);
+    }
+
+    // Other organizations still can't
+    let resp = router.oneshot(request(&other_org_token)).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+/// Applications resolved from paths are cached, until they are updated or deleted.
+async fn test_app_cache() {
+    async fn handler(
+        AuthenticatedOrganizationWithApplication { app, .. }: AuthenticatedOrganizationWithApplication,
+    ) -> String {
+        app.name
+    }
+
+    let mut cfg = get_default_test_config();
+    cfg.app_cache_ttl = 60;
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+    let pool = svix_server::db::init_db(&Arc::new(cfg.clone())).await;
+
+    let org_id = OrganizationId::generate();
+    let org_token = generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap();
+    let mut org_client = client.clone();
+    org_client.set_auth_header(org_token.clone());
+    let app: ApplicationOut = org_client
+        .post("api/v1/app/", application_in("A"), StatusCode::CREATED)
+        .await
+        .unwrap();
+
+    let router = Router::new()
+        .route("/app/:app_id/", get(handler))
+        .nest("/api/v1", svix_server::v1::endpoints::application::router())
+        .layer(Extension(pool.clone()))
+        .layer(Extension(svix_server::core::cache::memory::new()))
+        .layer(Extension(Arc::new(cfg)));
+    let request = |method: &str, uri: String, body: hyper::Body| {
+        http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", org_token))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap()
+    };
+    let get_name = || async {
+        let resp = router
+            .clone()
+            .oneshot(request(
+                "GET",
+                format!("/app/{}/", app.id),
+                hyper::Body::empty(),
+            ))
+            .await
+            .unwrap();
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    };
+
+    assert_eq!(get_name().await, (StatusCode::OK, "A".to_owned()));
+
+    // Renamed behind the server's back, so the cached application is used
+    let mut model: application::ActiveModel = application::Entity::find_by_id(app.id.clone())
+        .one(&pool)
+        .await
+        .unwrap()
+        .unwrap()
+        .into();
+    model.name = Set("B".to_owned());
+    model.update(&pool).await.unwrap();
+    assert_eq!(get_name().await, (StatusCode::OK, "A".to_owned()));
+
+    // Updating the application through the API invalidates it
+    let resp = router
+        .clone()
+        .oneshot(request(
+            "PUT",
+            format!("/api/v1/app/{}/", app.id),
+            serde_json::to_vec(&application_in("C")).unwrap().into(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(get_name().await, (StatusCode::OK, "C".to_owned()));
+
+    // As does deleting it
+    let resp = router
+        .clone()
+        .oneshot(request(
+            "DELETE",
+            format!("/api/v1/app/{}/", app.id),
+            hyper::Body::empty(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(get_name().await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+/// Organizations can mint application tokens for their own applications, but not for those of
+/// other organizations.
+async fn test_org_mints_app_token() {
+    let cfg = get_default_test_config();
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+    let pool = svix_server::db::init_db(&Arc::new(cfg.clone())).await;
+
+    let authenticated_org = |org_id: &OrganizationId| AuthenticatedOrganization {
+        permissions: Permissions::for_org(org_id.clone()),
+    };
+
+    let org_id = OrganizationId::generate();
+    let mut org_client = client.clone();
+    org_client.set_auth_header(generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap());
+    let app: ApplicationOut = org_client
+        .post(
+            "api/v1/app/",
+            application_in("TEST_APP_NAME"),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+
+    let token = authenticated_org(&org_id)
+        .generate_app_token(&cfg.jwt_secret, &pool, app.id.clone())
+        .await
+        .unwrap();
+    let mut app_client = client.clone();
+    app_client.set_auth_header(token);
+    let _: ApplicationOut = app_client
+        .get(&format!("api/v1/app/{}/", app.id), StatusCode::OK)
+        .await
+        .unwrap();
+
+    let err = authenticated_org(&OrganizationId::generate())
+        .generate_app_token(&cfg.jwt_secret, &pool, app.id)
+        .await
+        .unwrap_err();
+    assert_eq!(err.into_response().status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+/// Tokens limited to a single message can read that message and its delivery status, while
+/// everything else is hidden.
+async fn test_message_read_token() {
+    let cfg = get_default_test_config();
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+
+    let org_id = OrganizationId::generate();
+    let mut org_client = client.clone();
+    org_client.set_auth_header(generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap());
+    let app: ApplicationOut = org_client
+        .post(
+            "api/v1/app/",
+            application_in("TEST_APP_NAME"),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+    let msg = create_test_message(&org_client, &app.id, serde_json::json!({"test": "value"}))
+        .await
+        .unwrap();
+    let other_msg =
+        create_test_message(&org_client, &app.id, serde_json::json!({"test": "value2"}))
+            .await
+            .unwrap();
+
+    let mut msg_client = client.clone();
+    msg_client.set_auth_header(
+        generate_message_read_token(&cfg.jwt_secret, org_id, app.id.clone(), msg.id.clone())
+            .unwrap(),
+    );
+
+    // The allowed message and its delivery status
+    let read: MessageOut = msg_client
+        .get(
+            &format!("api/v1/app/{}/msg/{}/", app.id, msg.id),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    assert_eq!(read.id, msg.id);
+    let _: IgnoredResponse = msg_client
+        .get(
+            &format!("api/v1/app/{}/attempt/msg/{}/", app.id, msg.id),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+
+    // A different message is hidden
+    for path in [
+        format!("api/v1/app/{}/msg/{}/", app.id, other_msg.id),
+        format!("api/v1/app/{}/attempt/msg/{}/", app.id, other_msg.id),
+    ] {
+        let _: IgnoredResponse = msg_client.get(&path, StatusCode::NOT_FOUND).await.unwrap();
+    }
+
+    // As is everything else
+    for path in [
+        format!("api/v1/app/{}/", app.id),
+        format!("api/v1/app/{}/msg/", app.id),
+        format!("api/v1/app/{}/endpoint/", app.id),
+        "api/v1/event-type/".to_owned(),
+    ] {
+        let _: IgnoredResponse = msg_client.get(&path, StatusCode::NOT_FOUND).await.unwrap();
+    }
+}
+
+#[tokio::test]
+/// Application tokens with scopes may only be used for those, so a read-only token can't write.
+async fn test_scoped_app_token() {
+    let cfg = get_default_test_config();
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+
+    let org_id = OrganizationId::generate();
+    let mut org_client = client.clone();
+    org_client.set_auth_header(generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap());
+    let app: ApplicationOut = org_client
+        .post(
+            "api/v1/app/",
+            application_in("TEST_APP_NAME"),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+    let endp = create_test_endpoint(&org_client, &app.id, "http://localhost:1234/")
+        .await
+        .unwrap();
+
+    let mut read_client = client.clone();
+    read_client.set_auth_header(
+        generate_scoped_app_token(
+            &cfg.jwt_secret,
+            org_id.clone(),
+            app.id.clone(),
+            &["message:read", "endpoint:read"],
+        )
+        .unwrap(),
+    );
+
+    // Reading is allowed
+    for path in [
+        format!("api/v1/app/{}/msg/", app.id),
+        format!("api/v1/app/{}/endpoint/{}/", app.id, endp.id),
+    ] {
+        let _: IgnoredResponse = read_client.get(&path, StatusCode::OK).await.unwrap();
+    }
+
+    // Writing, or anything outside of the scopes, isn't
+    let _: IgnoredResponse = read_client
+        .post(
+            &format!("api/v1/app/{}/msg/", app.id),
+            message_in("event.type", serde_json::json!({"test": "value"})).unwrap(),
+            StatusCode::FORBIDDEN,
+        )
+        .await
+        .unwrap();
+    let _: IgnoredResponse = read_client
+        .put(
+            &format!("api/v1/app/{}/endpoint/{}/", app.id, endp.id),
+            endpoint_in("http://localhost:4321/"),
+            StatusCode::FORBIDDEN,
+        )
+        .await
+        .unwrap();
+    let _: IgnoredResponse = read_client
+        .get(&format!("api/v1/app/{}/", app.id), StatusCode::FORBIDDEN)
+        .await
+        .unwrap();
+
+    // While a token with the write scope can
+    let mut write_client = client.clone();
+    write_client.set_auth_header(
+        generate_scoped_app_token(&cfg.jwt_secret, org_id, app.id.clone(), &["message:write"])
+            .unwrap(),
+    );
+    create_test_message(&write_client, &app.id, serde_json::json!({"test": "value"}))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+/// A server serving one environment rejects tokens for any other.
+async fn test_token_environment() {
+    let mut cfg = get_default_test_config();
+    cfg.default_environment = "live".to_owned();
+    cfg.environment = Some("sandbox".to_owned());
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+
+    // Organization tokens without an `env` claim are for the default environment
+    let org_id = OrganizationId::generate();
+    let mut org_client = client.clone();
+    org_client.set_auth_header(generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap());
+    let _: IgnoredResponse = org_client
+        .get("api/v1/app/", StatusCode::FORBIDDEN)
+        .await
+        .unwrap();
+
+    let app_id = ApplicationId::generate();
+    let mut sandbox_client = client.clone();
+    sandbox_client.set_auth_header(
+        generate_app_token_for_env(&cfg.jwt_secret, org_id.clone(), app_id.clone(), "sandbox")
+            .unwrap(),
+    );
+    let mut live_client = client.clone();
+    live_client.set_auth_header(
+        generate_app_token_for_env(&cfg.jwt_secret, org_id, app_id.clone(), "live").unwrap(),
+    );
+
+    // A live token is rejected, while a sandbox one is let through (to find no such application)
+    let path = format!("api/v1/app/{}/", app_id);
+    let _: IgnoredResponse = live_client.get(&path, StatusCode::FORBIDDEN).await.unwrap();
+    let _: IgnoredResponse = sandbox_client
+        .get(&path, StatusCode::NOT_FOUND)
+        .await
+        .unwrap();
+}
@@ -3,12 +3,14 @@
 
 use std::{
     collections::{HashMap, HashSet},
+    sync::Arc,
     time::Duration,
 };
 
 use anyhow::Result;
 use chrono::Utc;
 use reqwest::StatusCode;
+use sea_orm::EntityTrait;
 
 use svix::webhooks::Webhook;
 use svix_server::{
@@ -16,6 +18,8 @@ use svix_server::{
         ApplicationId, EndpointHeaders, EndpointHeadersPatch, EndpointSecret, EndpointUid,
         EventChannel, EventChannelSet, EventTypeName, EventTypeNameSet, ExpiringSigningKeys,
     },
+    db::models::endpoint,
+    drained_endpoint_cleaner::clean_drained_endpoints,
     v1::{
         endpoints::{
             endpoint::{
@@ -37,8 +41,8 @@ use utils::{
         delete_test_app, endpoint_in, event_type_in, get_msg_attempt_list_and_assert_count,
         post_endpoint, put_endpoint, recover_webhooks,
     },
-    get_default_test_config, start_svix_server, start_svix_server_with_cfg, IgnoredResponse,
-    TestClient, TestReceiver,
+    get_default_test_config, run_with_retries, start_svix_server, start_svix_server_with_cfg,
+    IgnoredResponse, TestClient, TestReceiver,
 };
 
 async fn get_endpoint(
@@ -940,6 +944,7 @@ async fn test_msg_event_types_filter() {
                     payload: serde_json::json!({}),
                     uid: None,
                     payload_retention_period: 5,
+                    retry_schedule: None,
                 },
                 StatusCode::ACCEPTED,
             )
@@ -990,6 +995,7 @@ async fn test_msg_channels_filter() {
                     payload: serde_json::json!({}),
                     uid: None,
                     payload_retention_period: 5,
+                    retry_schedule: None,
                 },
                 StatusCode::ACCEPTED,
             )
@@ -1317,3 +1323,95 @@ async fn test_endpoint_https_only() {
         .await
         .unwrap();
 }
+
+#[tokio::test]
+async fn test_endpoint_delete_drains_pending_attempts() {
+    let mut cfg = get_default_test_config();
+    cfg.retry_schedule = vec![Duration::from_secs(2)];
+
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+    let pool = svix_server::db::init_db(&Arc::new(cfg)).await;
+
+    let app_id = create_test_app(&client, "app1").await.unwrap().id;
+
+    let mut receiver = TestReceiver::start(StatusCode::INTERNAL_SERVER_ERROR);
+
+    let endp = create_test_endpoint(&client, &app_id, &receiver.endpoint)
+        .await
+        .unwrap();
+
+    let msg = create_test_message(&client, &app_id, serde_json::json!({"test": "data1"}))
+        .await
+        .unwrap();
+
+    // Wait for the first (failing) attempt, and let the retry succeed
+    receiver.data_recv.recv().await.unwrap();
+    receiver.set_response_status_code(StatusCode::OK);
+
+    let _: IgnoredResponse = client
+        .delete(
+            &format!("api/v1/app/{}/endpoint/{}/?drain=true", app_id, endp.id),
+            StatusCode::NO_CONTENT,
+        )
+        .await
+        .unwrap();
+
+    // The endpoint is gone from the API right away...
+    let _ = get_endpoint_404(&client, &app_id, &endp.id).await.unwrap();
+
+    // ...but isn't removed while its retry is still pending
+    clean_drained_endpoints(&pool).await.unwrap();
+    let model = endpoint::Entity::find_by_id(endp.id.clone())
+        .one(&pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!model.deleted);
+    assert!(model.drain_deadline.is_some());
+
+    // The in-flight message is still delivered
+    receiver.data_recv.recv().await.unwrap();
+    get_msg_attempt_list_and_assert_count(&client, &app_id, &msg.id, 2)
+        .await
+        .unwrap();
+
+    run_with_retries(|| async {
+        clean_drained_endpoints(&pool).await?;
+        let model = endpoint::Entity::find_by_id(endp.id.clone())
+            .one(&pool)
+            .await?
+            .unwrap();
+        if !model.deleted {
+            anyhow::bail!("endpoint not deleted yet");
+        }
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    receiver.jh.abort();
+}
+
+#[tokio::test]
+async fn test_endpoint_delete_without_drain() {
+    let cfg = get_default_test_config();
+
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+    let pool = svix_server::db::init_db(&Arc::new(cfg)).await;
+
+    let app_id = create_test_app(&client, "app1").await.unwrap().id;
+
+    let endp = create_test_endpoint(&client, &app_id, "http://www.example.com")
+        .await
+        .unwrap();
+
+    delete_endpoint(&client, &app_id, &endp.id).await.unwrap();
+
+    let model = endpoint::Entity::find_by_id(endp.id.clone())
+        .one(&pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(model.deleted);
+    assert!(model.drain_deadline.is_none());
+}
@@ -0,0 +1,31 @@
+// SPDX-FileCopyrightText: © 2022 Svix Authors
+// SPDX-License-Identifier: MIT
+
+use reqwest::StatusCode;
+
+mod utils;
+use utils::{get_default_test_config, start_svix_server, start_svix_server_with_cfg};
+
+#[tokio::test]
+async fn test_health_signing_key() {
+    let (client, _jh) = start_svix_server();
+
+    let report: serde_json::Value = client.get("api/v1/health/", StatusCode::OK).await.unwrap();
+    assert_eq!(report["signing_key"]["status"], "ok");
+}
+
+#[tokio::test]
+/// An instance whose own tokens are rejected isn't ready
+async fn test_health_broken_signing_key() {
+    let mut cfg = get_default_test_config();
+    // Too short for any token to be accepted
+    cfg.jwt_max_header_length = 16;
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+
+    let report: serde_json::Value = client
+        .get("api/v1/health/", StatusCode::INTERNAL_SERVER_ERROR)
+        .await
+        .unwrap();
+    assert_eq!(report["signing_key"]["status"], "error");
+    assert_eq!(report["database"]["status"], "ok");
+}
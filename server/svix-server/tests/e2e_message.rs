@@ -6,18 +6,25 @@ use reqwest::StatusCode;
 use sea_orm::{sea_query::Expr, ColumnTrait, EntityTrait, QueryFilter};
 
 use svix_server::{
+    core::types::RetryScheduleOverride,
     db::models::message,
     expired_message_cleaner,
     v1::{
-        endpoints::attempt::MessageAttemptOut, endpoints::message::MessageOut, utils::ListResponse,
+        endpoints::attempt::MessageAttemptOut,
+        endpoints::message::{MessageIn, MessageOut},
+        utils::ListResponse,
     },
 };
 
 mod utils;
 
 use utils::{
-    common_calls::{create_test_app, create_test_endpoint, message_in},
-    run_with_retries, start_svix_server, TestReceiver,
+    common_calls::{
+        create_test_app, create_test_endpoint, create_test_message, event_type_in,
+        get_msg_attempt_list_and_assert_count, message_in,
+    },
+    get_default_test_config, run_with_retries, start_svix_server, start_svix_server_with_cfg,
+    IgnoredResponse, TestReceiver,
 };
 
 #[tokio::test]
@@ -391,3 +398,208 @@ async fn test_payload_retention_period() {
 
     assert_eq!(message.unwrap().payload, None);
 }
+
+#[tokio::test]
+async fn test_message_retry_schedule_override() {
+    let mut cfg = get_default_test_config();
+    // Long enough that no retry of the default schedule happens during the test
+    cfg.retry_schedule = vec![std::time::Duration::from_secs(60 * 60)];
+
+    let (client, _jh) = start_svix_server_with_cfg(&cfg);
+
+    let app_id = create_test_app(&client, "v1MessageRetryTestApp")
+        .await
+        .unwrap()
+        .id;
+
+    let receiver = TestReceiver::start(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    let _endp_id = create_test_endpoint(&client, &app_id, &receiver.endpoint)
+        .await
+        .unwrap()
+        .id;
+
+    let msg_default: MessageOut = client
+        .post(
+            &format!("api/v1/app/{}/msg/", &app_id),
+            message_in(&app_id, serde_json::json!({"test": "value"})).unwrap(),
+            StatusCode::ACCEPTED,
+        )
+        .await
+        .unwrap();
+
+    let msg_override: MessageOut = client
+        .post(
+            &format!("api/v1/app/{}/msg/", &app_id),
+            MessageIn {
+                retry_schedule: Some(RetryScheduleOverride::Schedule(vec![1])),
+                ..message_in(&app_id, serde_json::json!({"test": "value2"})).unwrap()
+            },
+            StatusCode::ACCEPTED,
+        )
+        .await
+        .unwrap();
+
+    // Retries are limited by the maximum schedule length
+    let too_long = vec![1; RetryScheduleOverride::MAX_ATTEMPTS + 1];
+    let _: IgnoredResponse = client
+        .post(
+            &format!("api/v1/app/{}/msg/", &app_id),
+            MessageIn {
+                retry_schedule: Some(RetryScheduleOverride::Schedule(too_long)),
+                ..message_in(&app_id, serde_json::json!({"test": "value3"})).unwrap()
+            },
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+        .await
+        .unwrap();
+
+    // Wait for the single retry of the override schedule (including jitter)
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    let list = get_msg_attempt_list_and_assert_count(&client, &app_id, &msg_override.id, 2)
+        .await
+        .unwrap();
+    assert!(list.data.iter().all(|x| x.custom_retry_schedule));
+
+    let list = get_msg_attempt_list_and_assert_count(&client, &app_id, &msg_default.id, 1)
+        .await
+        .unwrap();
+    assert!(list.data.iter().all(|x| !x.custom_retry_schedule));
+
+    receiver.jh.abort();
+}
+
+#[tokio::test]
+async fn test_message_event_type_payload_limits() {
+    let (client, _jh) = start_svix_server();
+
+    let app_id = create_test_app(&client, "v1MessageLimitsTestApp")
+        .await
+        .unwrap()
+        .id;
+
+    let mut constrained = event_type_in("constrained.event", serde_json::json!({})).unwrap();
+    constrained.max_payload_depth = Some(2);
+    constrained.max_payload_array_length = Some(3);
+    let _: IgnoredResponse = client
+        .post("api/v1/event-type", constrained, StatusCode::CREATED)
+        .await
+        .unwrap();
+
+    let _: IgnoredResponse = client
+        .post(
+            "api/v1/event-type",
+            event_type_in("unconstrained.event", serde_json::json!({})).unwrap(),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+
+    let too_deep = serde_json::json!({"a": {"b": {"c": 1}}});
+    let too_long = serde_json::json!({"a": [1, 2, 3, 4]});
+    let within = serde_json::json!({"a": {"b": 1}, "c": [1, 2, 3]});
+
+    for payload in [&too_deep, &too_long] {
+        let _: IgnoredResponse = client
+            .post(
+                &format!("api/v1/app/{}/msg/", &app_id),
+                message_in("constrained.event", payload).unwrap(),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            )
+            .await
+            .unwrap();
+    }
+
+    let _: MessageOut = client
+        .post(
+            &format!("api/v1/app/{}/msg/", &app_id),
+            message_in("constrained.event", &within).unwrap(),
+            StatusCode::ACCEPTED,
+        )
+        .await
+        .unwrap();
+
+    // Other event types are unaffected
+    for payload in [&too_deep, &too_long] {
+        let _: MessageOut = client
+            .post(
+                &format!("api/v1/app/{}/msg/", &app_id),
+                message_in("unconstrained.event", payload).unwrap(),
+                StatusCode::ACCEPTED,
+            )
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_message_no_matching_endpoints() {
+    let (client, _jh) = start_svix_server();
+
+    let app_id = create_test_app(&client, "noMatchingEndpointsApp")
+        .await
+        .unwrap()
+        .id;
+
+    // Messages to an application without endpoints are flagged
+    let msg = create_test_message(&client, &app_id, serde_json::json!({"test": "value"}))
+        .await
+        .unwrap();
+    assert!(msg.no_matching_endpoints);
+
+    let _: IgnoredResponse = client
+        .post(
+            &format!("api/v1/app/{}/endpoint/", &app_id),
+            serde_json::json!({
+                "url": "http://localhost:2/bad/url/",
+                "version": 1,
+                "channels": ["tag1"]
+            }),
+            StatusCode::CREATED,
+        )
+        .await
+        .unwrap();
+
+    let unmatched: MessageOut = client
+        .post(
+            &format!("api/v1/app/{}/msg/", &app_id),
+            serde_json::json!({
+                "eventType": "event.type",
+                "payload": {"test": "value"},
+                "channels": ["tag2"]
+            }),
+            StatusCode::ACCEPTED,
+        )
+        .await
+        .unwrap();
+    assert!(unmatched.no_matching_endpoints);
+
+    let matched: MessageOut = client
+        .post(
+            &format!("api/v1/app/{}/msg/", &app_id),
+            serde_json::json!({
+                "eventType": "event.type",
+                "payload": {"test": "value"},
+                "channels": ["tag1"]
+            }),
+            StatusCode::ACCEPTED,
+        )
+        .await
+        .unwrap();
+    assert!(!matched.no_matching_endpoints);
+
+    // The flag is kept on the read
+    for msg in [&unmatched, &matched] {
+        assert_eq!(
+            client
+                .get::<MessageOut>(
+                    &format!("api/v1/app/{}/msg/{}/", &app_id, &msg.id),
+                    StatusCode::OK
+                )
+                .await
+                .unwrap()
+                .no_matching_endpoints,
+            msg.no_matching_endpoints
+        );
+    }
+}
@@ -11,7 +11,8 @@ use svix_ksuid::KsuidLike;
 use svix_server::{
     cfg::ConfigurationInner,
     core::{
-        security::{generate_org_token, management_org_id},
+        auth_audit::NoopAuthAuditSink,
+        security::generate_org_token,
         types::{
             ApplicationId, ApplicationUid, BaseId, EndpointId, EndpointUid, MessageAttemptId,
             MessageId, MessageUid, OrganizationId,
@@ -102,9 +103,10 @@ fn start_svix_server_with_operational_webhooks(
     OrganizationId,
     tokio::task::JoinHandle<()>,
 ) {
-    let op_webhook_jwt = generate_org_token(&cfg.jwt_secret, management_org_id()).unwrap();
+    let op_webhook_jwt =
+        generate_org_token(&cfg.jwt_secret, cfg.management_org_id.clone()).unwrap();
 
-    let org_id = OrganizationId::new(None, None);
+    let org_id = OrganizationId::generate();
     let regular_jwt = generate_org_token(&cfg.jwt_secret, org_id.clone()).unwrap();
 
     let listener = TcpListener::bind("127.0.0.1:0").unwrap();
@@ -117,6 +119,7 @@ fn start_svix_server_with_operational_webhooks(
         Some(svix_ksuid::Ksuid::new(None, None).to_string()),
         cfg,
         Some(listener),
+        Arc::new(NoopAuthAuditSink),
     ));
 
     (
@@ -101,6 +101,7 @@ pub fn message_in<T: Serialize>(event_type: &str, payload: T) -> Result<MessageI
         payload_retention_period: 5,
         channels: None,
         uid: None,
+        retry_schedule: None,
     })
 }
 
@@ -124,6 +125,8 @@ pub fn event_type_in<T: Serialize>(name: &str, payload: T) -> Result<EventTypeIn
         description: "test-event-description".to_owned(),
         deleted: false,
         schemas: Some(serde_json::to_value(payload)?),
+        max_payload_depth: None,
+        max_payload_array_length: None,
     })
 }
 
@@ -16,6 +16,7 @@ use svix_ksuid::KsuidLike;
 use svix_server::{
     cfg::ConfigurationInner,
     core::{
+        auth_audit::NoopAuthAuditSink,
         security::generate_org_token,
         types::{BaseId, OrganizationId},
     },
@@ -235,7 +236,7 @@ pub fn start_svix_server_with_cfg(
 ) -> (TestClient, tokio::task::JoinHandle<()>) {
     let cfg = Arc::new(cfg.clone());
 
-    let token = generate_org_token(&cfg.jwt_secret, OrganizationId::new(None, None)).unwrap();
+    let token = generate_org_token(&cfg.jwt_secret, OrganizationId::generate()).unwrap();
     let listener = TcpListener::bind("127.0.0.1:0").unwrap();
     let base_uri = format!("http://{}", listener.local_addr().unwrap());
 
@@ -243,6 +244,7 @@ pub fn start_svix_server_with_cfg(
         Some(svix_ksuid::Ksuid::new(None, None).to_string()),
         cfg,
         Some(listener),
+        Arc::new(NoopAuthAuditSink),
     ));
 
     (TestClient::new(base_uri, &token), jh)